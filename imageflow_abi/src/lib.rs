@@ -39,6 +39,10 @@
 //! * An `imageflow_context` should ALWAYS be destroyed with `imageflow_context_destroy`
 //! * JsonResponse structures should be released with `imageflow_json_response_destroy`
 //! * An `imageflow_job` can be destroyed early with `imageflow_job_destroy`
+//! * A `ByteBuffer` is independent of the context - release it with `imageflow_byte_buffer_destroy`
+//!   whenever you're done with it, before or after the context is destroyed
+//! * A payload detached from a JsonResponse with `imageflow_json_response_detach` is likewise
+//!   independent of the context - release it with `imageflow_json_response_free`
 //!
 //! ## ... when allocated by the client, Imageflow only borrows it for the `invocation`
 //!
@@ -125,6 +129,8 @@ extern crate alloc_system;
 extern crate libc;
 extern crate smallvec;
 extern crate backtrace;
+#[macro_use]
+extern crate lazy_static;
 use c::ffi;
 
 pub use c::{Context, Job, FlowError, ErrorCategory, ErrorKind, CodeLocation};
@@ -265,6 +271,133 @@ macro_rules! handle_result {
         }}
 }
 
+// ------------------------------------------------------------------------------------------
+// ExternError out-parameter
+//
+// The plain entry points above require polling `imageflow_context_has_error` after every call,
+// and `context_ready!` aborts the process if you reuse a context that's already in an error
+// state. The `_ext`-suffixed entry points below instead accept a `*mut ExternError` out-param:
+// each call is self-contained and fallible, with no process-wide error state to poll or clear.
+// ------------------------------------------------------------------------------------------
+
+/// Reserved category for a panic caught by `catch_unwind`, distinct from any `ErrorCategory`
+/// value `to_c_error_code` can produce.
+const EXTERN_ERROR_INTERNAL_PANIC: i32 = -1;
+/// Reserved category for a null pointer passed where a required argument was expected.
+const EXTERN_ERROR_NULL_ARGUMENT: i32 = -2;
+/// Reserved category for a job that didn't run (or didn't finish) because its `CancelToken`
+/// was signalled, distinct from any `ErrorCategory` value `to_c_error_code` can produce.
+const EXTERN_ERROR_CANCELLED: i32 = -3;
+
+/// An owned, self-contained error result for the `_ext` entry points. On success, `code` is
+/// left 0 and `message` null. On failure, `code` holds the error's category (matching
+/// `ErrorCategory::to_c_error_code`), or one of the reserved negative codes:
+/// `EXTERN_ERROR_INTERNAL_PANIC` for a caught panic, `EXTERN_ERROR_NULL_ARGUMENT` for a null
+/// required argument, `EXTERN_ERROR_CANCELLED` for a cancelled async job. `message` is then an
+/// owned, heap-allocated UTF-8 C string - free it with `imageflow_error_free`.
+#[repr(C)]
+pub struct ExternError{
+    pub code: i32,
+    pub message: *mut libc::c_char,
+}
+impl ExternError{
+    fn success() -> ExternError{
+        ExternError{ code: 0, message: ptr::null_mut() }
+    }
+    fn from_message(code: i32, message: &str) -> ExternError{
+        let c_string = std::ffi::CString::new(message.replace('\0', "")).unwrap_or_else(|_| std::ffi::CString::new("").unwrap());
+        ExternError{ code: code, message: c_string.into_raw() }
+    }
+}
+
+unsafe fn write_extern_error(out_error: *mut ExternError, code: i32, message: &str){
+    if !out_error.is_null() {
+        *out_error = ExternError::from_message(code, message);
+    }
+}
+
+/// Frees the `message` string owned by an `ExternError` produced by an `_ext` entry point.
+/// Safe to call on a zeroed/success `ExternError` (null `message`).
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_error_free(err: *mut ExternError) {
+    if err.is_null() {
+        return;
+    }
+    let message = (*err).message;
+    if !message.is_null() {
+        let _ = std::ffi::CString::from_raw(message);
+        (*err).message = ptr::null_mut();
+    }
+}
+
+/// `_ext` equivalent of `imageflow_job_create`: instead of aborting on a null/errored context,
+/// writes a recoverable `ExternError` and returns null.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_job_create_ext(context: *mut Context, out_error: *mut ExternError) -> *mut Job {
+    if !out_error.is_null() {
+        *out_error = ExternError::success();
+    }
+    if context.is_null() {
+        write_extern_error(out_error, EXTERN_ERROR_NULL_ARGUMENT, "The argument 'context' is null.");
+        return ptr::null_mut();
+    }
+    let c = &mut *context;
+    let result = catch_unwind(AssertUnwindSafe(|| &mut *c.create_job() as *mut Job));
+    match result {
+        Ok(job) => job,
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic".to_owned());
+            write_extern_error(out_error, EXTERN_ERROR_INTERNAL_PANIC, &message);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// `_ext` equivalent of `imageflow_job_send_json`: recoverable failures (including a caught
+/// panic) are written to `out_error` instead of being stashed on the context, so the caller
+/// can recover without a separate polling call or risking an abort on reuse.
+#[no_mangle]
+#[allow(unused_variables)]
+pub unsafe extern "C" fn imageflow_job_send_json_ext(context: *mut Context,
+                                                     job: *mut Job,
+                                                     method: *const libc::c_char,
+                                                     json_buffer: *const u8,
+                                                     json_buffer_size: libc::size_t,
+                                                     out_error: *mut ExternError)
+                                                     -> *const JsonResponse {
+    if !out_error.is_null() {
+        *out_error = ExternError::success();
+    }
+    if context.is_null() || job.is_null() || method.is_null() || json_buffer.is_null() {
+        write_extern_error(out_error, EXTERN_ERROR_NULL_ARGUMENT, "A required argument was null.");
+        return ptr::null();
+    }
+    let c = &mut *context;
+    let panic_result = catch_unwind(AssertUnwindSafe(|| {
+        let method_str = CStr::from_ptr(method).to_str()
+            .map_err(|_| nerror!(ErrorKind::InvalidArgument, "The argument 'method' is invalid UTF-8."))?;
+        let json_bytes = std::slice::from_raw_parts(json_buffer, json_buffer_size);
+        let (json, result) = (&mut *job).message(method_str, json_bytes);
+        result.map(|_| create_abi_json_response(c, &json.response_json, json.status_code))
+    }));
+    match panic_result {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            write_extern_error(out_error, e.category().to_c_error_code(), &format!("{:?}", e));
+            ptr::null()
+        }
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic".to_owned());
+            write_extern_error(out_error, EXTERN_ERROR_INTERNAL_PANIC, &message);
+            ptr::null()
+        }
+    }
+}
+
 /// Creates and returns an imageflow context.
 /// An imageflow context is required for all other imageflow API calls.
 ///
@@ -493,9 +626,161 @@ pub unsafe extern fn imageflow_json_response_read(context: *mut Context,
 pub unsafe extern "C" fn imageflow_json_response_destroy(context: *mut Context,
                                                          response: *mut JsonResponse)
                                                          -> bool {
+    if !response.is_null() && !(*response).buffer_utf8_no_nulls.is_null() {
+        // The payload is a plain `libc::calloc` allocation (see `create_abi_json_response`),
+        // independent of the context's allocation tracking, so it's freed directly rather than
+        // through `imageflow_context_memory_free`. A detached response (see
+        // `imageflow_json_response_detach`) has a null `buffer_utf8_no_nulls` at this point, so
+        // it's skipped here - the caller owns it now.
+        libc::free((*response).buffer_utf8_no_nulls as *mut libc::c_void);
+    }
     imageflow_context_memory_free(context, response as *mut libc::c_void, ptr::null(), 0)
 }
 
+///
+/// Detaches `response`'s UTF-8 JSON payload from the context's allocation tracking and hands
+/// ownership of it directly to the caller: writes the payload pointer to `*out_buffer` and its
+/// length to `*out_len` (if non-null), and `response`'s status code to `*out_status` (if
+/// non-null). Release the detached payload - independently of `context`, even after
+/// `imageflow_context_destroy` - with `imageflow_json_response_free`.
+///
+/// Only the payload is detached; `response` itself is still owned by the context and should
+/// still be destroyed (if at all) with `imageflow_json_response_destroy`, which after a
+/// successful detach finds a null payload and has nothing left of its own to free.
+///
+/// Returns false (with an error on the context) if `response` or `out_buffer` is null, or if
+/// `response` was already detached.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_json_response_detach(context: *mut Context,
+                                                        response: *mut JsonResponse,
+                                                        out_buffer: *mut *mut u8,
+                                                        out_len: *mut libc::size_t,
+                                                        out_status: *mut i64)
+                                                        -> bool {
+    let c = context_ready!(context);
+    if response.is_null() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::NullArgument, "The argument 'response' is null."));
+        return false;
+    }
+    if out_buffer.is_null() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::NullArgument, "The argument 'out_buffer' is null."));
+        return false;
+    }
+    if (*response).buffer_utf8_no_nulls.is_null() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::InvalidArgument, "This JsonResponse has already been detached."));
+        return false;
+    }
+
+    *out_buffer = (*response).buffer_utf8_no_nulls;
+    if !out_len.is_null() {
+        *out_len = (*response).buffer_size;
+    }
+    if !out_status.is_null() {
+        *out_status = (*response).status_code;
+    }
+
+    (*response).buffer_utf8_no_nulls = ptr::null_mut();
+    (*response).buffer_size = 0;
+    true
+}
+
+///
+/// Frees a payload buffer previously detached with `imageflow_json_response_detach`. Tolerates
+/// a null `buffer`. Safe to call after `imageflow_context_destroy` - a detached payload owes
+/// nothing to the context that produced it.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_json_response_free(buffer: *mut u8) {
+    if !buffer.is_null() {
+        libc::free(buffer as *mut libc::c_void);
+    }
+}
+
+// ------------------------------------------------------------------------------------------
+// ByteBuffer - owned output that outlives the context
+//
+// `imageflow_json_response_read` and `imageflow_job_get_output_buffer_by_id` both hand back
+// pointers borrowed from the context; they dangle the moment the context is destroyed, which
+// forces bindings into a "copy before destroy" dance. The functions below instead hand the
+// caller a freshly allocated, independently-owned copy.
+// ------------------------------------------------------------------------------------------
+
+/// An owned buffer returned to the caller by value. Unlike the pointers produced by
+/// `imageflow_json_response_read` or `imageflow_job_get_output_buffer_by_id`, a `ByteBuffer`
+/// is not tied to the context's lifetime - free it with `imageflow_byte_buffer_destroy` whenever
+/// you're done with it, context or no context.
+#[repr(C)]
+pub struct ByteBuffer {
+    pub len: i64,
+    pub data: *mut u8,
+}
+
+/// Frees a `ByteBuffer` produced by this library. Tolerates a null `data` pointer (and any
+/// `len`), so a zeroed/default-initialized `ByteBuffer` is safe to pass here.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_byte_buffer_destroy(buffer: ByteBuffer) {
+    if !buffer.data.is_null() {
+        let _ = Vec::from_raw_parts(buffer.data, buffer.len as usize, buffer.len as usize);
+    }
+}
+
+fn vec_into_byte_buffer(mut bytes: Vec<u8>) -> ByteBuffer {
+    bytes.shrink_to_fit();
+    let buffer = ByteBuffer {
+        len: bytes.len() as i64,
+        data: bytes.as_mut_ptr(),
+    };
+    std::mem::forget(bytes);
+    buffer
+}
+
+/// Copies the `JsonResponse`'s bytes into a freshly allocated `ByteBuffer` that the caller owns
+/// independently of `context` or `response_in`; free it with `imageflow_byte_buffer_destroy`.
+/// Returns a zeroed `ByteBuffer` (null `data`) on failure; check the context for error details.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_json_response_into_buffer(context: *mut Context,
+                                                             response_in: *const JsonResponse)
+                                                             -> ByteBuffer {
+    let c = context_ready!(context);
+    if response_in.is_null() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::NullArgument, "The argument response_in (* JsonResponse) is null."));
+        return ByteBuffer { len: 0, data: ptr::null_mut() };
+    }
+    let bytes = std::slice::from_raw_parts((*response_in).buffer_utf8_no_nulls, (*response_in).buffer_size).to_vec();
+    vec_into_byte_buffer(bytes)
+}
+
+/// Copies the encoded output associated with `io_id` on `job` into a freshly allocated
+/// `ByteBuffer` that the caller owns independently of `context`; free it with
+/// `imageflow_byte_buffer_destroy`. Returns a zeroed `ByteBuffer` (null `data`) on failure;
+/// check the context for error details.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_job_get_output_buffer_by_id_into_buffer(context: *mut Context,
+                                                                           job: *mut Job,
+                                                                           io_id: i32)
+                                                                           -> ByteBuffer {
+    let mut c = context_ready!(context);
+    if job.is_null() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::NullArgument, "The argument 'job' is null."));
+        return ByteBuffer { len: 0, data: ptr::null_mut() };
+    }
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        (&*job).get_io(io_id).map_err(|e| e.at(here!())).and_then(|io_proxy| {
+            io_proxy.get_output_buffer_bytes().map(|s| s.to_vec()).map_err(|e| e.at(here!()))
+        })
+    }));
+    match result {
+        Ok(Ok(bytes)) => vec_into_byte_buffer(bytes),
+        Ok(Err(e)) => {
+            c.outward_error_mut().try_set_error(e);
+            ByteBuffer { len: 0, data: ptr::null_mut() }
+        }
+        Err(p) => {
+            c.outward_error_mut().try_set_panic_error(p);
+            ByteBuffer { len: 0, data: ptr::null_mut() }
+        }
+    }
+}
+
 ///
 /// Sends a JSON message to the imageflow_context
 ///
@@ -633,36 +918,38 @@ unsafe fn imageflow_send_json(context: *mut Context,
 }
 
 
+/// Allocates the `JsonResponse` header and its UTF-8 payload as two independent blocks instead
+/// of one combined allocation: the header comes from `flow_calloc` (context-tracked, freed
+/// alongside everything else `imageflow_context_memory_free`/`imageflow_json_response_destroy`
+/// track), while the payload is a plain `libc::calloc` allocation that owes nothing to the
+/// context's allocation tracking. That split is what lets `imageflow_json_response_detach` hand
+/// the payload to the caller on its own, independent of the context and the header, instead of
+/// forcing a second copy.
 pub fn create_abi_json_response(c: &mut Context,
                                 json_bytes: &[u8],
                                 status_code: i64)
                                 -> *const JsonResponse {
     unsafe {
-        let sizeof_struct = std::mem::size_of::<JsonResponse>();
-        let alloc_size = sizeof_struct + json_bytes.len();
-
-        let pointer = ::ffi::flow_context_calloc(c.flow_c(),
-                                                 1,
-                                                 alloc_size,
-                                                 ptr::null(),
-                                                 c.flow_c() as *mut libc::c_void,
-                                                 ptr::null(),
-                                                 line!() as i32) as *mut u8;
-        if pointer.is_null() {
-            c.outward_error_mut().try_set_error(nerror!(ErrorKind::AllocationFailed, "Failed to allocate JsonResponse ({} bytes)", alloc_size));
+        let header_size = std::mem::size_of::<JsonResponse>();
+
+        let header_ptr = flow_calloc(c, header_size, DEFAULT_ALLOCATION_ALIGNMENT, ptr::null(), line!() as i32) as *mut JsonResponse;
+        if header_ptr.is_null() {
+            c.outward_error_mut().try_set_error(nerror!(ErrorKind::AllocationFailed, "Failed to allocate JsonResponse ({} bytes)", header_size));
             return ptr::null();
         }
-        let pointer_to_final_buffer =
-            pointer.offset(sizeof_struct as isize) as *mut libc::uint8_t;
-        let imageflow_response = &mut (*(pointer as *mut JsonResponse));
-        imageflow_response.buffer_utf8_no_nulls = pointer_to_final_buffer;
-        imageflow_response.buffer_size = json_bytes.len();
-        imageflow_response.status_code = status_code;
 
-        let mut out_json_bytes = std::slice::from_raw_parts_mut(pointer_to_final_buffer,
-                                                                json_bytes.len());
+        let payload_ptr = libc::calloc(1, json_bytes.len().max(1)) as *mut u8;
+        if payload_ptr.is_null() {
+            flow_free(c, header_ptr as *mut libc::c_void, ptr::null(), line!() as i32);
+            c.outward_error_mut().try_set_error(nerror!(ErrorKind::AllocationFailed, "Failed to allocate the JsonResponse payload ({} bytes)", json_bytes.len()));
+            return ptr::null();
+        }
+        std::ptr::copy_nonoverlapping(json_bytes.as_ptr(), payload_ptr, json_bytes.len());
 
-        out_json_bytes.clone_from_slice(&json_bytes);
+        let imageflow_response = &mut *header_ptr;
+        imageflow_response.buffer_utf8_no_nulls = payload_ptr;
+        imageflow_response.buffer_size = json_bytes.len();
+        imageflow_response.status_code = status_code;
 
         imageflow_response as *const JsonResponse
     }
@@ -781,6 +1068,184 @@ pub unsafe extern "C" fn imageflow_io_create_from_buffer(context: *mut Context,
     handle_result!(c, result, ptr::null_mut())
 }
 
+// ------------------------------------------------------------------------------------------
+// Callback-based IO (vtable)
+//
+// `imageflow_io_create_for_file`, `imageflow_io_create_from_buffer`, and
+// `imageflow_io_create_for_output_buffer` only cover files and in-memory slices. There's no way
+// to decode/encode against a network socket, an HTTP range-request source, or a cloud object
+// store without buffering the whole asset first. `imageflow_io_create_from_callbacks` fills
+// that gap: it wraps a small C vtable - modeled on a minimal runtime I/O interface - and
+// trampolines `Read`/`Write`/`Seek` through it.
+// ------------------------------------------------------------------------------------------
+
+/// `seek`'s reference point, matching the `SeekOrigin`/`whence` convention used by most
+/// runtime I/O APIs.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum IoSeekOrigin {
+    Begin = 0,
+    Current = 1,
+    End = 2,
+}
+
+/// The C vtable backing a callback-based `imageflow_io`. Any function pointer may be left null
+/// if `IoMode` doesn't declare the corresponding capability; `imageflow_io_create_from_callbacks`
+/// rejects a `vtable` that's missing a function its `mode` claims to support.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct IoCallbacks {
+    /// Reads up to `len` bytes into `buffer`. Returns the number of bytes read (0 at EOF), or
+    /// a negative value on error.
+    pub read: Option<unsafe extern "C" fn(user_state: *mut libc::c_void, buffer: *mut u8, len: libc::size_t) -> i64>,
+    /// Writes up to `len` bytes from `buffer`. Returns the number of bytes written, or a
+    /// negative value on error.
+    pub write: Option<unsafe extern "C" fn(user_state: *mut libc::c_void, buffer: *const u8, len: libc::size_t) -> i64>,
+    /// Seeks `offset` bytes relative to `origin`. Returns the new absolute position, or a
+    /// negative value on error.
+    pub seek: Option<unsafe extern "C" fn(user_state: *mut libc::c_void, offset: i64, origin: IoSeekOrigin) -> i64>,
+    /// Returns the current position, or a negative value on error.
+    pub position: Option<unsafe extern "C" fn(user_state: *mut libc::c_void) -> i64>,
+    /// Returns the total length of the underlying resource, or a negative value if unknown or
+    /// on error.
+    pub length: Option<unsafe extern "C" fn(user_state: *mut libc::c_void) -> i64>,
+    /// Invoked exactly once, when the `imageflow_io` is cleaned up (per `CleanupWith`), so the
+    /// host can release whatever `user_state` refers to. May be null if nothing to release.
+    pub dispose: Option<unsafe extern "C" fn(user_state: *mut libc::c_void)>,
+}
+
+/// Backs a `JobIo` with a host-supplied `IoCallbacks` vtable instead of a file or in-memory
+/// buffer. Implements `Read`/`Write`/`Seek` by trampolining into the vtable, rejecting any
+/// operation not declared by the `IoMode` bits it was created with - regardless of which
+/// functions the vtable happens to supply.
+///
+/// There's no contiguous internal buffer backing this variant, so
+/// `imageflow_io_get_output_buffer`/`imageflow_job_get_output_buffer_by_id` return an error for
+/// it rather than a pointer.
+struct CallbackIo {
+    user_state: *mut libc::c_void,
+    mode_bits: i32,
+    callbacks: IoCallbacks,
+}
+// `user_state` is only ever touched through the vtable the host gave us; it's on the host to
+// make that safe across whatever threads it calls us from.
+unsafe impl Send for CallbackIo {}
+
+impl CallbackIo {
+    fn can(&self, capability: i32) -> bool {
+        self.mode_bits & capability != 0
+    }
+}
+
+impl Drop for CallbackIo {
+    fn drop(&mut self) {
+        if let Some(dispose) = self.callbacks.dispose {
+            unsafe { dispose(self.user_state); }
+        }
+    }
+}
+
+impl Write for CallbackIo {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.can(IoMode::WriteSequential as i32) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "This imageflow_io was not created with write capability."));
+        }
+        let write_fn = self.callbacks.write.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "The IoCallbacks vtable has no 'write' function."))?;
+        let result = unsafe { write_fn(self.user_state, buf.as_ptr(), buf.len()) };
+        if result < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("'write' returned error code {}", result)));
+        }
+        Ok(result as usize)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::io::Read for CallbackIo {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.can(IoMode::ReadSequential as i32) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "This imageflow_io was not created with read capability."));
+        }
+        let read_fn = self.callbacks.read.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "The IoCallbacks vtable has no 'read' function."))?;
+        let result = unsafe { read_fn(self.user_state, buf.as_mut_ptr(), buf.len()) };
+        if result < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("'read' returned error code {}", result)));
+        }
+        Ok(result as usize)
+    }
+}
+
+impl std::io::Seek for CallbackIo {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        const SEEKABLE: i32 = 4; // The shared bit in ReadSeekable/WriteSeekable/ReadWriteSeekable
+        if !self.can(SEEKABLE) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "This imageflow_io was not created with seek capability."));
+        }
+        let seek_fn = self.callbacks.seek.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "The IoCallbacks vtable has no 'seek' function."))?;
+        let (offset, origin) = match pos {
+            std::io::SeekFrom::Start(n) => (n as i64, IoSeekOrigin::Begin),
+            std::io::SeekFrom::Current(n) => (n, IoSeekOrigin::Current),
+            std::io::SeekFrom::End(n) => (n, IoSeekOrigin::End),
+        };
+        let result = unsafe { seek_fn(self.user_state, offset, origin) };
+        if result < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("'seek' returned error code {}", result)));
+        }
+        Ok(result as u64)
+    }
+}
+
+///
+/// Creates an imageflow_io backed by a host-supplied `IoCallbacks` vtable, for streaming
+/// against a socket, an HTTP range-request source, a cloud object store, or any other
+/// source/sink the host controls - without first buffering the whole asset into memory.
+///
+/// `mode` governs which of `vtable`'s functions are required: `read` for any read-capable
+/// mode, `write` for any write-capable mode, and `seek`/`position`/`length` for any seekable
+/// mode. `vtable.dispose` may be null if `user_state` needs no cleanup; otherwise it's invoked
+/// exactly once, when the `imageflow_io` is cleaned up (per `CleanupWith`).
+///
+/// `imageflow_io_get_output_buffer` and `imageflow_job_get_output_buffer_by_id` return an error
+/// for callback-backed I/O - there's no contiguous internal buffer to hand out a pointer to.
+///
+/// `user_state` is passed back to you, unmodified, as the first argument of every vtable call.
+/// You are responsible for keeping it valid until `vtable.dispose` runs (or forever, if
+/// `vtable.dispose` is null).
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_io_create_from_callbacks(context: *mut Context,
+                                                            mode: IoMode,
+                                                            user_state: *mut libc::c_void,
+                                                            vtable: *const IoCallbacks,
+                                                            cleanup: CleanupWith)
+                                                            -> *mut JobIo {
+    let mut c = context_ready!(context);
+    if vtable.is_null() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::NullArgument, "The argument 'vtable' is null."));
+        return ptr::null_mut();
+    }
+    let callbacks = *vtable;
+    let mode_bits = mode as i32;
+    if (mode_bits & (IoMode::ReadSequential as i32) != 0) && callbacks.read.is_none() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::InvalidArgument, "'mode' declares read capability, but 'vtable.read' is null."));
+        return ptr::null_mut();
+    }
+    if (mode_bits & (IoMode::WriteSequential as i32) != 0) && callbacks.write.is_none() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::InvalidArgument, "'mode' declares write capability, but 'vtable.write' is null."));
+        return ptr::null_mut();
+    }
+    if mode_bits & 4 != 0 && (callbacks.seek.is_none() || callbacks.position.is_none() || callbacks.length.is_none()) {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::InvalidArgument, "'mode' declares seek capability, but 'vtable.seek'/'vtable.position'/'vtable.length' is null."));
+        return ptr::null_mut();
+    }
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let callback_io = CallbackIo { user_state: user_state, mode_bits: mode_bits, callbacks: callbacks };
+        c.create_io_from_callbacks(Box::new(callback_io), cleanup).map_err(|e| e.at(here!()))
+    }));
+    let result = result.map(|r| r.map(|mut io| &mut *io as *mut JobIo));
+    handle_result!(c, result, ptr::null_mut())
+}
+
 
 ///
 /// Creates an imageflow_io structure for writing to an expanding memory buffer.
@@ -812,6 +1277,9 @@ pub unsafe extern "C" fn imageflow_io_create_for_output_buffer(context: *mut Con
 ///
 /// Ensure your length variable always holds 64-bits.
 ///
+/// Returns false (with an error on the context) for callback-backed I/O created with
+/// `imageflow_io_create_from_callbacks` - there's no contiguous internal buffer to point to.
+///
 #[no_mangle]
 pub unsafe extern "C" fn imageflow_io_get_output_buffer(context: *mut Context,
                                                         io: *mut JobIo,
@@ -848,6 +1316,9 @@ pub unsafe extern "C" fn imageflow_io_get_output_buffer(context: *mut Context,
 ///
 /// Ensure your length variable always holds 64-bits
 ///
+/// Returns false (with an error on the context) for callback-backed I/O created with
+/// `imageflow_io_create_from_callbacks` - there's no contiguous internal buffer to point to.
+///
 #[no_mangle]
 pub unsafe extern "C" fn imageflow_job_get_output_buffer_by_id(context: *mut Context,
                                                                job: *mut Job,
@@ -947,6 +1418,69 @@ pub unsafe extern "C" fn imageflow_job_add_io(context: *mut Context,
     handle_result!(c, result, false)
 }
 
+// ------------------------------------------------------------------------------------------
+// Progress reporting and cooperative cancellation
+//
+// `imageflow_job_send_json`/`_ext` dispatch `message()` synchronously with no way to observe
+// or abort a long encode/decode - painful for server and UI embedders running multi-megapixel
+// operations. `imageflow_job_set_progress_callback` registers a callback the job invokes
+// periodically during graph execution; returning `false` from it is a cooperative cancellation
+// request, unwinding `message()` to a clean state and failing it with
+// `ErrorKind::OperationCanceled` through the normal `JsonResponse`/outward-error path, same as
+// any other recoverable error.
+// ------------------------------------------------------------------------------------------
+
+/// C ABI signature for a job progress callback, registered with
+/// `imageflow_job_set_progress_callback`. Invoked periodically during graph execution with the
+/// units of work completed so far and the total expected; returning `false` requests
+/// cooperative cancellation of the in-progress `message()` call.
+pub type JobProgressCallback = unsafe extern "C" fn(user_state: *mut libc::c_void,
+                                                    completed_units: u64,
+                                                    total_units: u64)
+                                                    -> bool;
+
+/// Wraps a host-supplied `JobProgressCallback` so it can be stored on a `Job` and invoked from
+/// whichever thread is executing its graph, including an `imageflow_job_send_json_async`
+/// worker thread.
+struct JobProgressHook {
+    user_state: *mut libc::c_void,
+    callback: JobProgressCallback,
+}
+// `user_state` is only ever touched through the callback the host gave us; it's on the host to
+// make that safe across whatever thread we call it from.
+unsafe impl Send for JobProgressHook {}
+
+///
+/// Registers a progress callback on `job`, invoked periodically while it executes a graph (via
+/// any `imageflow_job_send_json*` variant) with the units of work completed so far and the
+/// total expected. Returning `false` from `callback` requests cooperative cancellation: the
+/// in-progress `message()` call unwinds to a clean state and fails with
+/// `ErrorKind::OperationCanceled` instead of completing.
+///
+/// Replaces whatever progress callback was previously registered on `job`. Pass a null
+/// `callback` to stop reporting progress.
+///
+/// `user_state` is passed back to you, unmodified, as `callback`'s first argument; you are
+/// responsible for keeping it valid for as long as `job` exists.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_job_set_progress_callback(context: *mut Context,
+                                                              job: *mut Job,
+                                                              user_state: *mut libc::c_void,
+                                                              callback: Option<JobProgressCallback>)
+                                                              -> bool {
+    let mut c = context_ready!(context);
+    if job.is_null() {
+        c.outward_error_mut().try_set_error(nerror!(ErrorKind::NullArgument, "The argument 'job' is null."));
+        return false;
+    }
+    let hook = callback.map(|callback| JobProgressHook { user_state: user_state, callback: callback });
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        (&mut *job).set_progress_callback(hook);
+        Ok(true)
+    }));
+    handle_result!(c, result, false)
+}
+
 ///
 /// Destroys the provided imageflow_job
 ///
@@ -973,7 +1507,7 @@ pub unsafe extern "C" fn imageflow_context_memory_allocate(context: *mut Context
                                                     line: i32) -> *mut libc::c_void {
 
     let mut c = context_ready!(context);
-    ffi::flow_context_calloc(c.flow_c(), 1, bytes, ptr::null(), c.flow_c() as *const libc::c_void, filename, line)
+    flow_calloc(c, bytes, DEFAULT_ALLOCATION_ALIGNMENT, filename, line)
 }
 
 ///
@@ -989,12 +1523,10 @@ pub unsafe extern "C" fn imageflow_context_memory_free(context: *mut Context,
                                                        pointer: *mut libc::c_void,
                                                        filename: *const libc::c_char,
                                                        line: i32) -> bool {
-    let flow_c_ptr = if context.is_null(){
-        ptr::null_mut()
-    }else {
-        (&mut *context).flow_c()
-    };
-    ffi::flow_destroy(flow_c_ptr, pointer, filename, line)
+    if context.is_null() {
+        return ffi::flow_destroy(ptr::null_mut(), pointer, filename, line);
+    }
+    flow_free(&mut *context, pointer, filename, line)
 }
 
 #[test]
@@ -1016,3 +1548,742 @@ fn test_allocate_free() {
         //imageflow_context_destroy(c);
     }
 }
+
+// ------------------------------------------------------------------------------------------
+// Host-supplied allocator
+//
+// `imageflow_context_memory_allocate`/`imageflow_context_memory_free` and
+// `create_abi_json_response` above all funnel through `flow_context_calloc`/`flow_destroy`,
+// which ultimately hit the process heap. Embedders running inside a managed runtime
+// (.NET/JVM), a sandbox, or an enclave need every byte the context touches to come from their
+// own allocator instead, so they can track, pool, or bound it.
+// `imageflow_context_set_allocator` lets a host register one; `flow_calloc`/`flow_free` below
+// dispatch to it when present and fall back to `ffi::flow_context_calloc`/`ffi::flow_destroy`
+// otherwise.
+// ------------------------------------------------------------------------------------------
+
+/// Default alignment requested for allocations made directly through the ABI layer
+/// (`flow_calloc` below).
+const DEFAULT_ALLOCATION_ALIGNMENT: libc::size_t = 16;
+
+/// C ABI signature for a host allocator's `alloc` hook, registered via
+/// `imageflow_context_set_allocator`. Must return `alignment`-aligned, zeroed memory (calloc
+/// semantics) of at least `bytes` length, or null on failure.
+pub type AllocatorAllocFn = unsafe extern "C" fn(user_state: *mut libc::c_void,
+                                                 bytes: libc::size_t,
+                                                 alignment: libc::size_t)
+                                                 -> *mut libc::c_void;
+
+/// C ABI signature for a host allocator's `free` hook. `ptr` was previously returned by this
+/// same vtable's `alloc_fn` or `realloc_fn`.
+pub type AllocatorFreeFn = unsafe extern "C" fn(user_state: *mut libc::c_void, ptr: *mut libc::c_void);
+
+/// C ABI signature for a host allocator's `realloc` hook. `ptr` was previously returned by this
+/// same vtable's `alloc_fn` or `realloc_fn`; the result must preserve `ptr`'s contents up to the
+/// smaller of the old and new sizes, or be null on failure (in which case `ptr` is left
+/// untouched and still owned by the caller).
+pub type AllocatorReallocFn = unsafe extern "C" fn(user_state: *mut libc::c_void,
+                                                   ptr: *mut libc::c_void,
+                                                   new_bytes: libc::size_t)
+                                                   -> *mut libc::c_void;
+
+/// A host-supplied allocator vtable, stored on the `Context` by `imageflow_context_set_allocator`.
+struct AllocatorVtable {
+    user_state: *mut libc::c_void,
+    alloc_fn: AllocatorAllocFn,
+    free_fn: AllocatorFreeFn,
+    realloc_fn: AllocatorReallocFn,
+}
+// `user_state` is only ever touched through the vtable the host gave us; it's on the host to
+// make that safe across whatever thread we call it from.
+unsafe impl Send for AllocatorVtable {}
+
+/// Routes through `c`'s registered allocator (see `imageflow_context_set_allocator`) if one is
+/// set, falling back to `ffi::flow_context_calloc` otherwise. `filename`/`line` are only used
+/// on the fallback path, for the same debugging purposes as `imageflow_context_memory_allocate`.
+unsafe fn flow_calloc(c: &mut Context,
+                     bytes: libc::size_t,
+                     alignment: libc::size_t,
+                     filename: *const libc::c_char,
+                     line: i32)
+                     -> *mut libc::c_void {
+    match c.allocator() {
+        Some(v) => (v.alloc_fn)(v.user_state, bytes, alignment),
+        None => ffi::flow_context_calloc(c.flow_c(), 1, bytes, ptr::null(), c.flow_c() as *const libc::c_void, filename, line),
+    }
+}
+
+/// Routes through `c`'s registered allocator if one is set, falling back to
+/// `ffi::flow_destroy` otherwise.
+unsafe fn flow_free(c: &mut Context, pointer: *mut libc::c_void, filename: *const libc::c_char, line: i32) -> bool {
+    match c.allocator() {
+        Some(v) => { (v.free_fn)(v.user_state, pointer); true }
+        None => ffi::flow_destroy(c.flow_c(), pointer, filename, line),
+    }
+}
+
+///
+/// Registers a host-supplied allocator for `context`. Once registered, allocations made
+/// through `imageflow_context_memory_allocate` and the `JsonResponse` headers built by
+/// `create_abi_json_response` come from `alloc_fn` (and are released through `free_fn`)
+/// instead of the process heap. `JsonResponse` payloads are plain `libc::calloc` allocations,
+/// so that `imageflow_json_response_detach` can hand them off, and node output buffers from
+/// the flow `BufferPool` come from `libc::malloc`/`libc::calloc`; neither goes through
+/// `alloc_fn`. Lets embedders in managed runtimes (.NET/JVM), sandboxes, or enclave-style
+/// environments track, pool, or bound the allocations that are routed through it.
+///
+/// Must be called immediately after `imageflow_context_create`, before `context` has made its
+/// first allocation. Calling it a second time, or after an allocation has already occurred,
+/// fails and leaves whichever allocator (host-supplied or the process heap) was already in
+/// effect untouched.
+///
+/// `alloc_fn` must return zeroed memory (calloc semantics) aligned to the requested
+/// `alignment` - this layer requests `DEFAULT_ALLOCATION_ALIGNMENT` (16 bytes), but the host's
+/// allocator needs to honor whatever `alignment` it's given, not just a single fixed value.
+///
+/// Returns false (with an error on the context) if `alloc_fn`, `free_fn`, or `realloc_fn` is
+/// null, or if an allocator is already in use for this context.
+///
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_context_set_allocator(context: *mut Context,
+                                                         user_state: *mut libc::c_void,
+                                                         alloc_fn: Option<AllocatorAllocFn>,
+                                                         free_fn: Option<AllocatorFreeFn>,
+                                                         realloc_fn: Option<AllocatorReallocFn>)
+                                                         -> bool {
+    let mut c = context_ready!(context);
+    let (alloc_fn, free_fn, realloc_fn) = match (alloc_fn, free_fn, realloc_fn) {
+        (Some(a), Some(f), Some(r)) => (a, f, r),
+        _ => {
+            c.outward_error_mut().try_set_error(nerror!(ErrorKind::NullArgument, "'alloc_fn', 'free_fn', and 'realloc_fn' must all be non-null."));
+            return false;
+        }
+    };
+    let vtable = AllocatorVtable { user_state: user_state, alloc_fn: alloc_fn, free_fn: free_fn, realloc_fn: realloc_fn };
+    match c.set_allocator(vtable) {
+        Ok(()) => true,
+        Err(e) => {
+            c.outward_error_mut().try_set_error(e.at(here!()));
+            false
+        }
+    }
+}
+
+#[test]
+fn test_set_allocator_rejects_null_callbacks() {
+    unsafe {
+        let c = imageflow_context_create();
+        assert!(!imageflow_context_set_allocator(c, ptr::null_mut(), None, None, None));
+        assert!(imageflow_context_has_error(c));
+        assert!(imageflow_context_error_try_clear(c));
+        imageflow_context_destroy(c);
+    }
+}
+
+// ------------------------------------------------------------------------------------------
+// Handle-based API
+//
+// The pointer-based functions above abort the process on a null `Context` pointer, and a
+// stale or dangling one is undefined behavior - they have no way to tell a valid pointer from
+// one whose context is already gone. Hosts that can't guarantee pointer validity (GC'd languages, sandboxes
+// that move or collect memory out from under us) need something safer: an opaque integer
+// handle that can be validated before anything is dereferenced, and that reports a recoverable
+// error instead of aborting when it's stale, foreign, or concurrently in use.
+// ------------------------------------------------------------------------------------------
+
+use std::sync::Mutex;
+
+/// An opaque handle returned by `imageflow_context_create_handle`/`imageflow_job_create_handle`.
+/// Packs `(map_id: 16 bits) | (generation: 16 bits) | (index: 32 bits)`. `map_id` prevents a
+/// `Job` handle from being presented where a `Context` handle is expected; `generation` detects
+/// use of a handle whose slot has since been freed and reused.
+pub type Handle = u64;
+
+const HANDLE_INDEX_BITS: u32 = 32;
+const HANDLE_GENERATION_BITS: u32 = 16;
+
+const CONTEXT_MAP_ID: u16 = 1;
+const JOB_MAP_ID: u16 = 2;
+
+fn pack_handle(map_id: u16, generation: u16, index: u32) -> Handle {
+    ((map_id as u64) << (HANDLE_INDEX_BITS + HANDLE_GENERATION_BITS) as u64)
+        | ((generation as u64) << HANDLE_INDEX_BITS as u64)
+        | (index as u64)
+}
+fn unpack_handle(handle: Handle) -> (u16, u16, u32) {
+    let index = (handle & 0xFFFF_FFFF) as u32;
+    let generation = ((handle >> HANDLE_INDEX_BITS) & 0xFFFF) as u16;
+    let map_id = (handle >> (HANDLE_INDEX_BITS + HANDLE_GENERATION_BITS)) as u16;
+    (map_id, generation, index)
+}
+
+/// Recoverable outcomes of a handle lookup; returned instead of aborting the process.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HandleError{
+    Ok = 0,
+    NullOrInvalidHandle = 1,
+    WrongHandleMap = 2,
+    HandleStale = 3,
+    HandleInUse = 4,
+}
+
+struct HandleSlot<T>{
+    value: Option<T>,
+    generation: u16,
+    in_use: bool,
+}
+
+/// A generational slot map keyed by `Handle`. Inserting into a freed slot bumps its
+/// generation, so a handle minted before the free is rejected (`HandleStale`) instead of
+/// aliasing whatever was inserted afterward.
+struct HandleMap<T>{
+    map_id: u16,
+    slots: Vec<HandleSlot<T>>,
+    free_list: Vec<u32>,
+}
+impl<T> HandleMap<T>{
+    fn new(map_id: u16) -> HandleMap<T>{
+        HandleMap{ map_id: map_id, slots: Vec::new(), free_list: Vec::new() }
+    }
+
+    fn insert(&mut self, value: T) -> Handle{
+        let index = match self.free_list.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.generation = slot.generation.wrapping_add(1);
+                slot.value = Some(value);
+                slot.in_use = false;
+                index
+            }
+            None => {
+                let index = self.slots.len() as u32;
+                self.slots.push(HandleSlot{ value: Some(value), generation: 0, in_use: false });
+                index
+            }
+        };
+        pack_handle(self.map_id, self.slots[index as usize].generation, index)
+    }
+
+    /// Looks up `handle`'s slot, checking ownership (map id, generation) and availability.
+    /// `in_use` - set by a `with` closure currently running, or by a `checkout` that hasn't
+    /// been `checkin`'d yet - is checked *before* `value`, since a checked-out slot's value is
+    /// `None` precisely because it's in use: if `value.is_none()` were checked first, a
+    /// checked-out handle would always read as stale and `HandleInUse` could never be returned.
+    fn slot_for(&mut self, handle: Handle) -> Result<&mut HandleSlot<T>, HandleError>{
+        let (map_id, generation, index) = unpack_handle(handle);
+        if map_id != self.map_id {
+            return Err(HandleError::WrongHandleMap);
+        }
+        let slot = self.slots.get_mut(index as usize).ok_or(HandleError::NullOrInvalidHandle)?;
+        if slot.generation != generation {
+            return Err(HandleError::HandleStale);
+        }
+        if slot.in_use {
+            return Err(HandleError::HandleInUse);
+        }
+        if slot.value.is_none() {
+            return Err(HandleError::HandleStale);
+        }
+        Ok(slot)
+    }
+
+    /// Looks up `handle`, marks its slot in-use for the duration of `f`, and clears the flag
+    /// afterward. Returns `HandleInUse` instead of running `f` if the slot is already checked
+    /// out, so two threads driving the same handle concurrently get a distinct error rather
+    /// than racing on whatever `f` touches. Indexes `self.slots` directly (rather than calling
+    /// `slot_for` again) once `in_use` is set, since `slot_for` itself would now reject our own
+    /// in-progress call.
+    fn with<R, F: FnOnce(&mut T) -> R>(&mut self, handle: Handle, f: F) -> Result<R, HandleError>{
+        let index = {
+            let slot = self.slot_for(handle)?;
+            slot.in_use = true;
+            unpack_handle(handle).2
+        };
+        let result = f(self.slots[index as usize].value.as_mut().unwrap());
+        self.slots[index as usize].in_use = false;
+        Ok(result)
+    }
+
+    /// Marks `handle`'s slot in-use and removes its value from the map, handing ownership to
+    /// the caller. Unlike `with`, the value leaves the map for as long as the caller holds it
+    /// (e.g. for the duration of a job running on a worker thread) instead of only for a single
+    /// synchronous closure - the mutex guarding this map is not held in the meantime. Pair with
+    /// `checkin` to return the value and clear the flag; `remove` rejects a checked-out handle
+    /// the same way it rejects a stale one, since its slot's value is also `None`.
+    fn checkout(&mut self, handle: Handle) -> Result<T, HandleError>{
+        let slot = self.slot_for(handle)?;
+        slot.in_use = true;
+        Ok(slot.value.take().unwrap())
+    }
+
+    /// Returns a value removed by `checkout` to its slot and clears the in-use flag.
+    fn checkin(&mut self, handle: Handle, value: T) -> Result<(), HandleError>{
+        let (map_id, generation, index) = unpack_handle(handle);
+        if map_id != self.map_id {
+            return Err(HandleError::WrongHandleMap);
+        }
+        let slot = self.slots.get_mut(index as usize).ok_or(HandleError::NullOrInvalidHandle)?;
+        if slot.generation != generation {
+            return Err(HandleError::HandleStale);
+        }
+        slot.value = Some(value);
+        slot.in_use = false;
+        Ok(())
+    }
+
+    /// Finalizes a handle previously taken out with `checkout`: frees its slot for reuse (at a
+    /// bumped generation) without requiring a value to hand back, for a caller that checked a
+    /// value out in order to destroy it rather than return it via `checkin`. Held `in_use` the
+    /// whole time since the matching `checkout`, so this never races a concurrent destroy of the
+    /// same handle the way re-deriving `in_use` from a fresh lookup would.
+    fn finish_checkout(&mut self, handle: Handle) -> Result<(), HandleError>{
+        let (map_id, generation, index) = unpack_handle(handle);
+        if map_id != self.map_id {
+            return Err(HandleError::WrongHandleMap);
+        }
+        let slot = self.slots.get_mut(index as usize).ok_or(HandleError::NullOrInvalidHandle)?;
+        if slot.generation != generation {
+            return Err(HandleError::HandleStale);
+        }
+        slot.in_use = false;
+        self.free_list.push(index);
+        Ok(())
+    }
+
+    /// Removes and returns the slot's value, freeing the index for reuse (at a bumped
+    /// generation) by a future `insert`.
+    fn remove(&mut self, handle: Handle) -> Result<T, HandleError>{
+        let index = {
+            let (map_id, generation, index) = unpack_handle(handle);
+            if map_id != self.map_id {
+                return Err(HandleError::WrongHandleMap);
+            }
+            let slot = self.slots.get_mut(index as usize).ok_or(HandleError::NullOrInvalidHandle)?;
+            if slot.generation != generation || slot.value.is_none() {
+                return Err(HandleError::HandleStale);
+            }
+            index
+        };
+        let value = self.slots[index as usize].value.take().unwrap();
+        self.free_list.push(index);
+        Ok(value)
+    }
+}
+
+/// A job handle's payload: jobs are owned by their `Context` (as with the pointer-based API),
+/// so all the handle map stores is the raw pointer the context handed back plus the handle of
+/// the owning context, for revalidation before every use.
+#[derive(Clone, Copy)]
+struct JobHandleEntry{
+    context_handle: Handle,
+    job: *mut Job,
+}
+
+lazy_static! {
+    static ref CONTEXT_HANDLES: Mutex<HandleMap<Box<Context>>> = Mutex::new(HandleMap::new(CONTEXT_MAP_ID));
+    static ref JOB_HANDLES: Mutex<HandleMap<JobHandleEntry>> = Mutex::new(HandleMap::new(JOB_MAP_ID));
+}
+
+/// Creates a context and returns a generational handle to it rather than a raw pointer.
+/// Returns 0 (never a valid handle - real handles always carry a non-zero map id) on
+/// allocation failure.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_context_create_handle() -> Handle {
+    match Context::create_cant_panic() {
+        Some(b) => CONTEXT_HANDLES.lock().unwrap().insert(b),
+        None => 0,
+    }
+}
+
+/// Destroys a context created with `imageflow_context_create_handle`.
+///
+/// Returns `HandleError::Ok` (0) on success, or a recoverable error code - never aborts the
+/// process, even if `handle` is stale, foreign to this map, or already destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_context_destroy_by_handle(handle: Handle) -> HandleError {
+    match CONTEXT_HANDLES.lock().unwrap().remove(handle) {
+        Ok(_) => HandleError::Ok,
+        Err(e) => e,
+    }
+}
+
+/// Creates a job within the context referenced by `context_handle` and returns a generational
+/// handle to it. Returns 0 and leaves `out_error` set on failure (including a stale/foreign
+/// `context_handle`) instead of aborting.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_job_create_handle(context_handle: Handle, out_error: *mut HandleError) -> Handle {
+    let result = CONTEXT_HANDLES.lock().unwrap().with(context_handle, |c: &mut Box<Context>| {
+        &mut *c.create_job() as *mut Job
+    });
+    match result {
+        Ok(job_ptr) => JOB_HANDLES.lock().unwrap().insert(JobHandleEntry{ context_handle: context_handle, job: job_ptr }),
+        Err(e) => {
+            if !out_error.is_null() {
+                *out_error = e;
+            }
+            0
+        }
+    }
+}
+
+/// Destroys a job created with `imageflow_job_create_handle`.
+///
+/// Returns `HandleError::Ok` (0) on success, or a recoverable error code. Because the job is
+/// owned by its context, this asks the context to tear the job down *before* freeing the job's
+/// own handle slot - if the context is busy (e.g. mid-`imageflow_job_send_json_async`) and
+/// rejects the teardown, the job handle stays valid so the caller can retry instead of the job
+/// leaking with no handle left to reference it by.
+///
+/// `checkout`s the job's slot up front and holds it checked out for the whole sequence (rather
+/// than only for the instant it's read via `with`), so a second concurrent call against the same
+/// handle gets `HandleError::HandleInUse` from its own `checkout` immediately, instead of also
+/// reading `entry` and racing this call to tear down the same job twice.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_job_destroy_by_handle(handle: Handle) -> HandleError {
+    let entry = match JOB_HANDLES.lock().unwrap().checkout(handle) {
+        Ok(entry) => entry,
+        Err(e) => return e,
+    };
+    let teardown = CONTEXT_HANDLES.lock().unwrap().with(entry.context_handle, |c: &mut Box<Context>| {
+        c.abi_try_remove_job(entry.job)
+    });
+    match teardown {
+        Ok(_) => {
+            match JOB_HANDLES.lock().unwrap().finish_checkout(handle) {
+                Ok(_) => HandleError::Ok,
+                Err(e) => e,
+            }
+        }
+        Err(e) => {
+            // The context rejected the teardown (or was itself unreachable) - put the entry
+            // back so the handle stays valid for a retry instead of leaking it checked out
+            // forever.
+            let _ = JOB_HANDLES.lock().unwrap().checkin(handle, entry);
+            e
+        }
+    }
+}
+
+/// Handle-based equivalent of `imageflow_context_has_error`: rather than aborting on an
+/// invalid/stale `context_handle`, writes the answer to `out_has_error` and returns
+/// `HandleError::Ok`, or returns a recoverable error code and leaves `out_has_error` untouched.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_context_has_error_by_handle(context_handle: Handle, out_has_error: *mut bool) -> HandleError {
+    let result = CONTEXT_HANDLES.lock().unwrap().with(context_handle, |c: &mut Box<Context>| {
+        c.outward_error_mut().has_error()
+    });
+    match result {
+        Ok(has_error) => {
+            if !out_has_error.is_null() {
+                *out_has_error = has_error;
+            }
+            HandleError::Ok
+        }
+        Err(e) => e,
+    }
+}
+
+// ------------------------------------------------------------------------------------------
+// Async job execution
+//
+// Contexts are explicitly single-threaded - the pointer-based API leaves synchronizing calls
+// per context to the caller, and the handle API's `HandleError::HandleInUse` enforces "one call at a time" cooperatively. But
+// a potentially long encode/decode has no supported way to run off the caller's thread. The
+// functions below move a context handle's `Box<Context>` onto a worker thread for the duration
+// of one `imageflow_job_send_json` call, reusing the handle map's in-use flag (via
+// `HandleMap::checkout`/`checkin`) to keep that same "one call at a time per context" contract
+// while the call is in flight, and invoke a callback on completion instead of blocking.
+// ------------------------------------------------------------------------------------------
+
+/// Cooperative cancellation flag for an async job started with `imageflow_job_send_json_async`.
+/// Checked at pipeline node boundaries (and before the job starts) so a stalled or runaway job
+/// can abort early and report `EXTERN_ERROR_CANCELLED` instead of running to completion.
+/// Create with `imageflow_cancel_token_create`, signal with
+/// `imageflow_cancel_token_request_cancel`, and release with `imageflow_cancel_token_destroy`.
+pub struct CancelToken {
+    cancelled: std::sync::atomic::AtomicBool,
+}
+impl CancelToken {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+/// Creates a `CancelToken` in the not-cancelled state. Free it with
+/// `imageflow_cancel_token_destroy` once the async job it was passed to has completed.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_cancel_token_create() -> *mut CancelToken {
+    Box::into_raw(Box::new(CancelToken { cancelled: std::sync::atomic::AtomicBool::new(false) }))
+}
+
+/// Requests cancellation of whatever async job `token` was passed to. Safe to call from any
+/// thread, including the one waiting on the completion callback. Has no effect on a null token,
+/// or if the job has already finished.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_cancel_token_request_cancel(token: *mut CancelToken) {
+    if !token.is_null() {
+        (*token).cancelled.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// Frees a `CancelToken` created with `imageflow_cancel_token_create`. Don't call this until
+/// the completion callback for every async job it was passed to has run.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_cancel_token_destroy(token: *mut CancelToken) {
+    if !token.is_null() {
+        let _ = Box::from_raw(token);
+    }
+}
+
+/// Invoked exactly once, on the worker thread, when an `imageflow_job_send_json_async` call
+/// completes. `response` follows the same ownership rules as the synchronous `_ext` entry
+/// points - it (and `error.message`, via `imageflow_error_free`) must be freed by the host.
+pub type JobSendJsonAsyncCallback = unsafe extern "C" fn(callback_state: *mut libc::c_void,
+                                                         response: *const JsonResponse,
+                                                         error: ExternError);
+
+/// Handle-based equivalent of `imageflow_job_send_json_ext` that runs off the calling thread.
+///
+/// `context_handle` is checked out from the context handle map for the duration of the call
+/// (see `HandleMap::checkout`), so any other API call against it - on this thread or another -
+/// fails with `HandleError::HandleInUse` instead of racing, exactly as `with` already does for
+/// synchronous handle calls; the context is checked back in immediately before `callback` runs.
+///
+/// `method` and the `json_buffer` range are only borrowed for the duration of this function
+/// call (they're copied before the worker thread starts) - unlike the synchronous entry points,
+/// they do not need to remain valid until `callback` fires.
+///
+/// If `cancel_token` is non-null and already cancelled (or becomes cancelled before the job
+/// finishes), `callback` receives a null `response` and an `ExternError` with code
+/// `EXTERN_ERROR_CANCELLED` instead of running (or finishing) the job.
+///
+/// Returns `HandleError::Ok` (0) immediately after the worker thread is spawned - this is NOT
+/// the result of the job itself, which always arrives via `callback`. A non-`Ok` return means
+/// no worker thread was started and `callback` will never be invoked for this call.
+#[no_mangle]
+pub unsafe extern "C" fn imageflow_job_send_json_async(context_handle: Handle,
+                                                       job_handle: Handle,
+                                                       method: *const libc::c_char,
+                                                       json_buffer: *const u8,
+                                                       json_buffer_size: libc::size_t,
+                                                       callback: JobSendJsonAsyncCallback,
+                                                       callback_state: *mut libc::c_void,
+                                                       cancel_token: *mut CancelToken)
+                                                       -> HandleError {
+    if method.is_null() || json_buffer.is_null() {
+        return HandleError::NullOrInvalidHandle;
+    }
+    let method_string = match CStr::from_ptr(method).to_str() {
+        Ok(s) => s.to_owned(),
+        Err(_) => return HandleError::NullOrInvalidHandle,
+    };
+    let json_owned = std::slice::from_raw_parts(json_buffer, json_buffer_size).to_vec();
+
+    let (owning_context_handle, job_ptr) =
+        match JOB_HANDLES.lock().unwrap().with(job_handle, |entry: &mut JobHandleEntry| (entry.context_handle, entry.job)) {
+            Ok(pair) => pair,
+            Err(e) => return e,
+        };
+    if owning_context_handle != context_handle {
+        return HandleError::WrongHandleMap;
+    }
+
+    let mut context_box = match CONTEXT_HANDLES.lock().unwrap().checkout(context_handle) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+
+    // Raw pointers/fn pointers aren't `Send`; smuggle them across as addresses and reconstitute
+    // them on the worker thread, which is the only place they're used.
+    let job_addr = job_ptr as usize;
+    let callback_addr = callback as usize;
+    let callback_state_addr = callback_state as usize;
+    let cancel_token_addr = cancel_token as usize;
+
+    std::thread::spawn(move || {
+        let job = job_addr as *mut Job;
+        let callback: JobSendJsonAsyncCallback = std::mem::transmute(callback_addr);
+        let callback_state = callback_state_addr as *mut libc::c_void;
+        let cancel_token = cancel_token_addr as *mut CancelToken;
+
+        let already_cancelled = !cancel_token.is_null() && (*cancel_token).is_cancelled();
+
+        let (response, error) = if already_cancelled {
+            (ptr::null(), ExternError::from_message(EXTERN_ERROR_CANCELLED, "The job was cancelled before it started."))
+        } else {
+            if !cancel_token.is_null() {
+                context_box.set_cancel_token(&*cancel_token);
+            }
+            let panic_result = catch_unwind(AssertUnwindSafe(|| {
+                let (json, result) = (&mut *job).message(&method_string, &json_owned);
+                result.map(|_| create_abi_json_response(&mut *context_box, &json.response_json, json.status_code))
+            }));
+            match panic_result {
+                Ok(Ok(response)) => (response, ExternError::success()),
+                Ok(Err(e)) => {
+                    let cancelled = !cancel_token.is_null() && (*cancel_token).is_cancelled();
+                    let code = if cancelled { EXTERN_ERROR_CANCELLED } else { e.category().to_c_error_code() };
+                    (ptr::null(), ExternError::from_message(code, &format!("{:?}", e)))
+                }
+                Err(panic) => {
+                    let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "Unknown panic".to_owned());
+                    (ptr::null(), ExternError::from_message(EXTERN_ERROR_INTERNAL_PANIC, &message))
+                }
+            }
+        };
+
+        // Check the context back in before invoking the callback, so a host that immediately
+        // issues another call against it from within the callback doesn't spuriously race
+        // `HandleError::HandleInUse`.
+        let _ = CONTEXT_HANDLES.lock().unwrap().checkin(context_handle, context_box);
+
+        callback(callback_state, response, error);
+    });
+
+    HandleError::Ok
+}
+
+/// Test fixture for `imageflow_job_send_json_async`'s `callback`: records whether a response
+/// came back and the error code via `tx`, and bumps `call_count` so tests can assert the
+/// callback fired exactly once.
+struct AsyncTestCallbackState{
+    tx: std::sync::mpsc::Sender<(usize, i32)>,
+    call_count: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+unsafe extern "C" fn async_test_callback(callback_state: *mut libc::c_void,
+                                         response: *const JsonResponse,
+                                         mut error: ExternError) {
+    let state = Box::from_raw(callback_state as *mut AsyncTestCallbackState);
+    state.call_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    let code = error.code;
+    imageflow_error_free(&mut error);
+    let _ = state.tx.send((response as usize, code));
+}
+
+#[test]
+fn test_send_json_async_cancel_before_start() {
+    unsafe {
+        let context_handle = imageflow_context_create_handle();
+        assert!(context_handle != 0);
+        let mut create_error = HandleError::Ok;
+        let job_handle = imageflow_job_create_handle(context_handle, &mut create_error);
+        assert!(job_handle != 0);
+
+        let token = imageflow_cancel_token_create();
+        imageflow_cancel_token_request_cancel(token);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let call_count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let state = Box::new(AsyncTestCallbackState{ tx: tx, call_count: call_count.clone() });
+
+        let method = static_char!("brew_coffee");
+        let json = "{}";
+        let result = imageflow_job_send_json_async(context_handle, job_handle, method,
+                                                   json.as_ptr(), json.len(),
+                                                   async_test_callback,
+                                                   Box::into_raw(state) as *mut libc::c_void,
+                                                   token);
+        assert_eq!(result, HandleError::Ok);
+
+        let (response_addr, code) = rx.recv_timeout(std::time::Duration::from_secs(5))
+            .expect("callback never fired");
+        assert_eq!(response_addr, 0);
+        assert_eq!(code, EXTERN_ERROR_CANCELLED);
+        assert_eq!(call_count.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        imageflow_cancel_token_destroy(token);
+        imageflow_job_destroy_by_handle(job_handle);
+        imageflow_context_destroy_by_handle(context_handle);
+    }
+}
+
+#[test]
+fn test_send_json_async_cancel_mid_flight() {
+    unsafe {
+        let context_handle = imageflow_context_create_handle();
+        assert!(context_handle != 0);
+        let mut create_error = HandleError::Ok;
+        let job_handle = imageflow_job_create_handle(context_handle, &mut create_error);
+        assert!(job_handle != 0);
+
+        let token = imageflow_cancel_token_create();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let call_count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let state = Box::new(AsyncTestCallbackState{ tx: tx, call_count: call_count.clone() });
+
+        let method = static_char!("brew_coffee");
+        let json = "{}";
+        let result = imageflow_job_send_json_async(context_handle, job_handle, method,
+                                                   json.as_ptr(), json.len(),
+                                                   async_test_callback,
+                                                   Box::into_raw(state) as *mut libc::c_void,
+                                                   token);
+        assert_eq!(result, HandleError::Ok);
+
+        // Races the worker thread, which may finish `brew_coffee` (a near-instant stub
+        // response, with no graph nodes to dispatch) before this ever runs - so this can't
+        // force a landing, only observe one when the race goes the other way. Since
+        // `job_execute_where_certain` now checks `Context::is_cancelled` once per dispatched
+        // batch (see `flow/mod.rs`) rather than never, a cancellation requested against a job
+        // that's actually mid-execution does land and short-circuits with
+        // `ErrorKind::OperationCanceled`/`EXTERN_ERROR_CANCELLED`; what this test asserts is
+        // that requesting cancellation mid-flight never panics or double-fires the callback,
+        // and that whichever outcome lands is reported consistently (no response iff cancelled).
+        imageflow_cancel_token_request_cancel(token);
+
+        let (response_addr, code) = rx.recv_timeout(std::time::Duration::from_secs(5))
+            .expect("callback never fired");
+        if code == EXTERN_ERROR_CANCELLED {
+            assert_eq!(response_addr, 0);
+        } else {
+            assert_eq!(code, 0);
+            assert!(response_addr != 0);
+        }
+        assert_eq!(call_count.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        imageflow_cancel_token_destroy(token);
+        imageflow_job_destroy_by_handle(job_handle);
+        imageflow_context_destroy_by_handle(context_handle);
+    }
+}
+
+#[test]
+fn test_send_json_async_callback_fires_exactly_once() {
+    unsafe {
+        let context_handle = imageflow_context_create_handle();
+        assert!(context_handle != 0);
+        let mut create_error = HandleError::Ok;
+        let job_handle = imageflow_job_create_handle(context_handle, &mut create_error);
+        assert!(job_handle != 0);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let call_count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let state = Box::new(AsyncTestCallbackState{ tx: tx, call_count: call_count.clone() });
+
+        let method = static_char!("brew_coffee");
+        let json = "{}";
+        let result = imageflow_job_send_json_async(context_handle, job_handle, method,
+                                                   json.as_ptr(), json.len(),
+                                                   async_test_callback,
+                                                   Box::into_raw(state) as *mut libc::c_void,
+                                                   ptr::null_mut());
+        assert_eq!(result, HandleError::Ok);
+
+        let (response_addr, code) = rx.recv_timeout(std::time::Duration::from_secs(5))
+            .expect("callback never fired");
+        assert_eq!(code, 0);
+        assert!(response_addr != 0);
+
+        // No second message ever arrives for this call.
+        assert!(rx.recv_timeout(std::time::Duration::from_millis(50)).is_err());
+        assert_eq!(call_count.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        imageflow_job_destroy_by_handle(job_handle);
+        imageflow_context_destroy_by_handle(context_handle);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A 128-bit content digest over a node and everything it depends on - a Merkle hash over the
+/// subgraph feeding a node, built from two independent 64-bit hashes so a collision in one half
+/// doesn't imply one in the other.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeDigest(pub u64, pub u64);
+
+impl NodeDigest {
+    /// Seeds a digest from any hashable value - typically a node's type plus its
+    /// parameters/info bytes (and, for decoder nodes, the source bytes' hash).
+    pub fn of<T: Hash>(value: &T) -> NodeDigest {
+        let mut a = DefaultHasher::new();
+        value.hash(&mut a);
+        let mut b = DefaultHasher::new();
+        0xD1B54A32D192ED03u64.hash(&mut b); // distinct seed, so the second half isn't just a's hash again
+        value.hash(&mut b);
+        NodeDigest(a.finish(), b.finish())
+    }
+
+    /// Folds `parent`'s digest into this one. Order-sensitive (swapping two inputs almost
+    /// always changes the result), so callers must combine parents in a stable order -
+    /// dependency-wise traversal order, which the graph walker already guarantees.
+    pub fn combine(self, parent: NodeDigest) -> NodeDigest {
+        let mut a = DefaultHasher::new();
+        self.0.hash(&mut a);
+        parent.0.hash(&mut a);
+        let mut b = DefaultHasher::new();
+        self.1.hash(&mut b);
+        parent.1.hash(&mut b);
+        NodeDigest(a.finish(), b.finish())
+    }
+}
+
+/// Storage backend for cached node outputs, keyed by `NodeDigest`. A node whose digest is a
+/// cache hit can skip straight to `NodeStage::Executed` without re-running its operation -
+/// identical parameters and identical inputs always produce the same digest, and changing
+/// anything upstream changes it automatically.
+pub trait ResultCache: Send + Sync {
+    /// Returns the cached bitmap bytes for `digest`, if present.
+    fn get(&self, digest: NodeDigest) -> Option<Vec<u8>>;
+    /// Stores `bitmap` under `digest`, evicting older entries if the backend is bounded.
+    fn put(&self, digest: NodeDigest, bitmap: Vec<u8>);
+}
+
+struct LruState {
+    map: HashMap<NodeDigest, Vec<u8>>,
+    // Least-recently-used at the front, most-recently-used at the back.
+    order: Vec<NodeDigest>,
+}
+
+/// A bounded in-memory cache that evicts the least-recently-used entry once `capacity` is
+/// exceeded - the default backend, suitable for a single long-running process generating many
+/// thumbnail/variant jobs from the same sources.
+pub struct InMemoryLruCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+impl InMemoryLruCache {
+    pub fn with_capacity(capacity: usize) -> InMemoryLruCache {
+        InMemoryLruCache {
+            capacity: capacity,
+            state: Mutex::new(LruState { map: HashMap::new(), order: Vec::new() }),
+        }
+    }
+
+    fn touch(order: &mut Vec<NodeDigest>, digest: NodeDigest) {
+        order.retain(|d| *d != digest);
+        order.push(digest);
+    }
+}
+
+impl ResultCache for InMemoryLruCache {
+    fn get(&self, digest: NodeDigest) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let hit = state.map.get(&digest).cloned();
+        if hit.is_some() {
+            InMemoryLruCache::touch(&mut state.order, digest);
+        }
+        hit
+    }
+
+    fn put(&self, digest: NodeDigest, bitmap: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        if !state.map.contains_key(&digest) && state.map.len() >= self.capacity && !state.order.is_empty() {
+            let oldest = state.order.remove(0);
+            state.map.remove(&oldest);
+        }
+        InMemoryLruCache::touch(&mut state.order, digest);
+        state.map.insert(digest, bitmap);
+    }
+}
+
+/// An on-disk cache rooted at a directory, one file per digest - survives across process runs,
+/// e.g. a CLI regenerating the same thumbnails on every invocation. Misses (including I/O
+/// errors) are treated as cache misses rather than failures, matching the "pluggable, best
+/// effort" nature of a cache.
+pub struct DiskResultCache {
+    root: PathBuf,
+}
+
+impl DiskResultCache {
+    pub fn new(root: PathBuf) -> DiskResultCache {
+        DiskResultCache { root: root }
+    }
+
+    fn path_for(&self, digest: NodeDigest) -> PathBuf {
+        self.root.join(format!("{:016x}{:016x}.flowcache", digest.0, digest.1))
+    }
+}
+
+impl ResultCache for DiskResultCache {
+    fn get(&self, digest: NodeDigest) -> Option<Vec<u8>> {
+        File::open(self.path_for(digest)).ok().and_then(|mut f| {
+            let mut bytes = Vec::new();
+            match f.read_to_end(&mut bytes) {
+                Ok(_) => Some(bytes),
+                Err(_) => None,
+            }
+        })
+    }
+
+    fn put(&self, digest: NodeDigest, bitmap: Vec<u8>) {
+        let _ = fs::create_dir_all(&self.root);
+        if let Ok(mut f) = File::create(self.path_for(digest)) {
+            let _ = f.write_all(&bitmap);
+        }
+    }
+}
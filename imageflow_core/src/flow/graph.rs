@@ -0,0 +1,133 @@
+use daggy;
+use daggy::Dag;
+use daggy::walker::Walker;
+use petgraph::graph::node_index;
+use std::collections::HashSet;
+use super::cache::NodeDigest;
+use super::definitions::*;
+
+/// One node's lifecycle stage, inferred/estimated output size, and per-pass cost accounting -
+/// the state the graph walker and the optimization/dimension passes read and update directly.
+/// Node-type-specific parameters live alongside this wherever `nodes` stores them.
+pub struct NodeData {
+    pub stage: NodeStage,
+    pub frame_est: FrameEstimate,
+    pub cost: NodeCost,
+    /// Seed for this node's `ResultCache` digest - hashes its type and parameters/info bytes
+    /// (and, for decoder nodes, the source bytes' hash). Set wherever `nodes` constructs the
+    /// node; the walker folds in parent digests on top of this to get the full Merkle hash.
+    pub type_digest_seed: NodeDigest,
+    /// The digest this node was last executed (or cache-hit) under, once `stage` reaches
+    /// `NodeStage::Executed`. `None` beforehand.
+    pub cache_digest: Option<NodeDigest>,
+    /// Bytes the scheduler has reserved/charged against `memory_budget_bytes` for this node's
+    /// output buffer. Zero once every outbound consumer has read it (see `pending_consumers`)
+    /// or before the node has run at all.
+    pub reserved_bytes: u64,
+    /// Remaining outbound (non-`EdgeKind::None`) consumers that still need this node's output.
+    /// Set when the node executes; the scheduler releases `reserved_bytes` once it reaches zero.
+    pub pending_consumers: u32,
+    /// This node's output buffer, if it came from the job's `BufferPool` - the raw pointer (as
+    /// `usize`, since `NodeData` otherwise holds no raw pointers) and its bucket capacity.
+    /// Returned to the pool's free list in lockstep with `pending_consumers` reaching zero.
+    pub pooled_buffer: Option<(usize, usize)>,
+}
+
+/// The execution graph: a DAG of nodes (pixel operations) connected by edges describing how
+/// one node's output feeds another's input.
+pub type Graph = Dag<NodeData, EdgeKind, u32>;
+
+/// The two ways a node visitor can influence the rest of `walk_dependency_wise`'s traversal,
+/// mirroring the `quit`/`skip_outbound_paths` out-parameters of the original C
+/// `flow_graph_walk`/`flow_graph_walk_dependency_wise`.
+pub struct VisitControl {
+    /// Set to prune every node reachable only through the node just visited from the rest of
+    /// *this* walk - their dependencies aren't satisfiable (or aren't ready) yet. They're
+    /// reconsidered on the next `re_walk` pass, if the caller is looping to a fixpoint.
+    pub skip_outbound_paths: bool,
+    /// Set to abort the entire walk immediately, leaving any not-yet-visited nodes unvisited.
+    pub quit: bool,
+}
+
+impl Default for VisitControl {
+    fn default() -> VisitControl {
+        VisitControl { skip_outbound_paths: false, quit: false }
+    }
+}
+
+/// Walks `graph` in dependency order - a node is only visited once every parent reachable via
+/// a non-`EdgeKind::None` edge has itself already been visited (or pruned) - invoking
+/// `visit(graph, node, &mut control)` once per node. This is the generic engine behind
+/// `flow_graph_walk_dependency_wise`: callers like `job_populate_dimensions_where_certain` or
+/// `job_execute_where_certain` supply `visit` as a closure over whatever node-specific logic
+/// they need.
+///
+/// `visit` returns `Ok(true)` if it mutated the graph in a way that might unblock a later
+/// `re_walk` pass (see `walk_dependency_wise_to_fixpoint`), `Ok(false)` if not, and `Err(())`
+/// on a hard failure, which stops the walk immediately without visiting whatever's left.
+///
+/// A node whose visit sets `control.skip_outbound_paths` is treated as pruned: its descendants
+/// are skipped for the rest of *this* walk (not permanently - a fresh `walk_dependency_wise`
+/// call reconsiders everything). `control.quit` aborts the whole walk, visited or not.
+pub fn walk_dependency_wise<F>(graph: &mut Graph, mut visit: F) -> Result<bool, ()>
+    where F: FnMut(&mut Graph, daggy::NodeIndex<u32>, &mut VisitControl) -> Result<bool, ()>
+{
+    let mut visited: HashSet<daggy::NodeIndex<u32>> = HashSet::new();
+    let mut pruned: HashSet<daggy::NodeIndex<u32>> = HashSet::new();
+    let mut mutated = false;
+
+    loop {
+        let ready_node = graph.raw_nodes().iter().enumerate()
+            .map(|(i, _)| node_index(i))
+            .find(|&node| {
+                !visited.contains(&node) && !pruned.contains(&node) &&
+                graph.parents(node).iter(graph).all(|(edge, parent)| {
+                    *graph.edge_weight(edge).unwrap() == EdgeKind::None ||
+                    visited.contains(&parent) || pruned.contains(&parent)
+                })
+            });
+
+        let node = match ready_node {
+            Some(n) => n,
+            None => break, // Nothing left is ready; whatever remains was pruned this walk.
+        };
+
+        let depends_on_pruned = graph.parents(node).iter(graph).any(|(edge, parent)| {
+            *graph.edge_weight(edge).unwrap() != EdgeKind::None && pruned.contains(&parent)
+        });
+        if depends_on_pruned {
+            // A dependency was pruned (not visited) - this node is unreachable this walk too.
+            pruned.insert(node);
+            continue;
+        }
+
+        let mut control = VisitControl::default();
+        let changed = visit(graph, node, &mut control)?;
+        mutated = mutated || changed;
+        visited.insert(node);
+
+        if control.quit {
+            break;
+        }
+        if control.skip_outbound_paths {
+            pruned.insert(node);
+        }
+    }
+
+    Ok(mutated)
+}
+
+/// Repeats `walk_dependency_wise` until a full pass mutates nothing - the `re_walk` loop the C
+/// code threaded through `custom_data`. Termination is guaranteed on an acyclic graph: each
+/// non-dry pass either finishes the graph or makes progress a finite number of node-states can
+/// absorb.
+pub fn walk_dependency_wise_to_fixpoint<F>(graph: &mut Graph, mut visit: F) -> Result<(), ()>
+    where F: FnMut(&mut Graph, daggy::NodeIndex<u32>, &mut VisitControl) -> Result<bool, ()>
+{
+    loop {
+        let mutated = walk_dependency_wise(graph, &mut visit)?;
+        if !mutated {
+            return Ok(());
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use ffi::*;
-use libc::{self, int32_t, c_void};
+use ErrorKind;
+use libc::{self, int32_t};
 use std::ffi::CStr;
 use std::fs::File;
 use std::io::Write;
@@ -9,10 +10,17 @@ use petgraph::graph::node_index;
 use time;
 
 pub mod graph;
+pub mod cache;
+pub mod pool;
+pub mod scheduler;
+pub mod snapshot;
 pub mod definitions;
 pub mod nodes;
-use self::graph::Graph;
+use self::cache::NodeDigest;
+use self::graph::{Graph, VisitControl, walk_dependency_wise_to_fixpoint};
 use self::definitions::*;
+use std::collections::HashSet;
+use std::thread;
 
 #[macro_export]
 macro_rules! error_return (
@@ -154,68 +162,43 @@ pub fn job_link_codecs(c: *mut Context, job: *mut Job, graph_ref: &mut Graph) ->
     return true;
 }
 
+/// The C version bounded recording with a `FLOW_MAX_GRAPH_VERSIONS` macro that isn't part of
+/// this tree; `MAX_GRAPH_VERSIONS` is the same kind of sanity cap, just as a plain constant.
+const MAX_GRAPH_VERSIONS: int32_t = 256;
+
+/// Debug-only: when `job.record_graph_versions` is set, snapshots `graph_ref`'s current
+/// stage/edge structure and - unlike the old C implementation's byte-for-byte `.dot` file
+/// compare - diffs it *structurally* against the last recorded version via
+/// `snapshot::structurally_equal`. A structural no-op (e.g. a pass that ran but didn't change
+/// anything) isn't persisted, so the flatten/optimize/execute loop in `job_execute` produces a
+/// clean sequence of distinct versions instead of one entry per pass invocation.
 fn job_notify_graph_changed(c: *mut Context, job: *mut Job, graph_ref: &mut Graph) -> bool {
-/* FIXME
-    if (job == NULL || !job->record_graph_versions || job->next_graph_version > FLOW_MAX_GRAPH_VERSIONS)
+    if job.is_null() || !unsafe { (*job).record_graph_versions } ||
+       unsafe { (*job).next_graph_version } > MAX_GRAPH_VERSIONS {
         return true;
-
-    char filename[255];
-    char image_prefix[255];
-    char prev_filename[255];
-
-    if (job->next_graph_version == 0) {
-        // Delete existing graphs
-        int32_t i = 0;
-        for (i = 0; i <= FLOW_MAX_GRAPH_VERSIONS; i++) {
-            flow_snprintf(filename, 254, "job_%d_graph_version_%d.dot", job->debug_job_id, i);
-            remove(filename);
-            flow_snprintf(filename, 254, "job_%d_graph_version_%d.dot.png", job->debug_job_id, i);
-            remove(filename);
-            flow_snprintf(filename, 254, "job_%d_graph_version_%d.dot.svg", job->debug_job_id, i);
-            remove(filename);
-            int32_t node_ix = 0;
-            for (node_ix = 0; node_ix < 42; node_ix++) {
-                flow_snprintf(filename, 254, "./node_frames/job_%d_node_%d.png", job->debug_job_id, node_ix);
-                remove(filename);
-            }
-        }
     }
 
-    int32_t prev_graph_version = job->next_graph_version - 1;
-    int32_t current_graph_version = job->next_graph_version;
-    job->next_graph_version++;
+    let current_snapshot = snapshot::snapshot_graph(graph_ref);
 
-    flow_snprintf(filename, 254, "job_%d_graph_version_%d.dot", job->debug_job_id, current_graph_version);
-
-    flow_snprintf(image_prefix, 254, "./node_frames/job_%d_node_", job->debug_job_id);
-
-    FILE * f = fopen(filename, "w");
-    if (f == NULL) {
-        FLOW_error_msg(c, flow_status_IO_error, "Failed to open %s for graph dotfile export.", filename);
-        return false;
+    let is_duplicate_of_last = unsafe { (*job).recorded_graph_snapshots.last() }
+        .map(|prev| snapshot::structurally_equal(prev, &current_snapshot.graph))
+        .unwrap_or(false);
+    if is_duplicate_of_last {
+        return true;
     }
-    if (!flow_graph_print_to_dot(c, g, f, image_prefix)) {
-        fclose(f);
-        FLOW_error_return(c);
-    } else {
-        fclose(f);
+
+    let current_graph_version = unsafe { (*job).next_graph_version };
+    unsafe {
+        (*job).recorded_graph_snapshots.push(current_snapshot.graph);
+        (*job).next_graph_version += 1;
     }
-    // Compare
-    if (job->next_graph_version > 1) {
-        flow_snprintf(prev_filename, 254, "job_%d_graph_version_%d.dot", job->debug_job_id, prev_graph_version);
-        bool identical = false;
-        if (!files_identical(c, prev_filename, filename, &identical)) {
-            FLOW_error_return(c);
-        }
-        if (identical) {
-            job->next_graph_version--; // Next time we will overwrite the duplicate graph. The last two graphs may
-            // remain dupes.
-            remove(filename);
-        } else if (job->render_graph_versions) {
-            flow_job_render_graph_to_png(c, job, g, prev_graph_version);
+
+    if unsafe { (*job).render_graph_versions } {
+        if !job_render_graph_to_png(c, job, graph_ref, current_graph_version) {
+            error_return!(c);
+            return false;
         }
     }
-*/
     return true;
 }
 
@@ -232,120 +215,252 @@ pub fn job_graph_fully_executed(c: *mut Context, job: *mut Job, graph_ref: &mut
 
 pub fn job_populate_dimensions_where_certain(c:*mut Context, job: *mut Job, graph_ref: &mut Graph) -> bool
 {
-    /*
     // TODO: would be good to verify graph is acyclic.
-    if (!flow_graph_walk_dependency_wise(c, job, graph_ref, node_visitor_dimensions, NULL, (void *)false)) {
-        FLOW_error_return(c);
+    // Iterate to a fixpoint: populating one node's dimensions can be exactly what unblocks
+    // a sibling branch that was pruned via skip_outbound_paths on an earlier pass.
+    match walk_dependency_wise_to_fixpoint(graph_ref, |g, node, control| {
+        node_visitor_dimensions(c, job, g, node, control, false)
+    }) {
+        Ok(()) => true,
+        Err(()) => { error_return!(c); false }
     }
-    */
-    return true;
 }
 
 pub fn graph_pre_optimize_flatten(c: *mut Context, graph_ref: &mut Graph) -> bool
 {
-    /*FIXME: is it still needed?
-    if unsafe {(*graph_ref).is_null()} {
-        error_msg!(c, FlowStatusCode::NullArgument);
-        return false;
+    match walk_dependency_wise_to_fixpoint(graph_ref, |g, node, control| {
+        node_visitor_flatten(c, g, node, control)
+    }) {
+        Ok(()) => true,
+        Err(()) => { error_return!(c); false }
     }
-    */
-    /*FIXME
-    bool re_walk;
-    do {
-        re_walk = false;
-        if (!flow_graph_walk_dependency_wise(c, NULL, graph_ref, node_visitor_flatten, NULL, &re_walk)) {
-            FLOW_error_return(c);
-        }
-    } while (re_walk);
-    */
-    return true;
 }
 
 pub fn graph_optimize(c: *mut Context,job: *mut Job, graph_ref: &mut Graph) -> bool
 {
-    /*FIXME: is it still needed?
-    if unsafe { (*graph_ref).is_null()} {
-        error_msg!(c, FlowStatusCode::NullArgument);
-        return false;
+    match walk_dependency_wise_to_fixpoint(graph_ref, |g, node, control| {
+        node_visitor_optimize(c, job, g, node.index() as int32_t, control)
+    }) {
+        Ok(()) => true,
+        Err(()) => { error_return!(c); false }
     }
-    */
-    /*FIXME
-    bool re_walk;
-    do {
-        re_walk = false;
-        if (!flow_graph_walk(c, job, graph_ref, node_visitor_optimize, NULL, &re_walk)) {
-            FLOW_error_return(c);
-        }
-    } while (re_walk);
-    */
-    return true;
 }
 
 pub fn graph_post_optimize_flatten(c: *mut Context, job: *mut Job, graph_ref: &mut Graph) -> bool
 {
-    /*FIXME: is it still needed?
-    if unsafe { (*graph_ref).is_null()} {
-        error_msg!(c, FlowStatusCode::NullArgument);
-        return false;
+    match walk_dependency_wise_to_fixpoint(graph_ref, |g, node, control| {
+        node_visitor_post_optimize_flatten(c, job, g, node, control)
+    }) {
+        Ok(()) => true,
+        Err(()) => { error_return!(c); false }
     }
-    */
-
-    /*FIXME
-    bool re_walk;
-    do {
-        re_walk = false;
-        if (!flow_graph_walk(c, job, graph_ref, node_visitor_post_optimize_flatten, NULL, &re_walk)) {
-            FLOW_error_return(c);
-        }
-    } while (re_walk);
-    */
-    return true;
 }
 
 pub fn job_execute_where_certain(c: *mut Context, job: *mut Job, graph_ref: &mut Graph) -> bool
 {
-    /*FIXME: is it still needed?
-    if unsafe { (*graph_ref).is_null()} {
-        error_msg!(c, FlowStatusCode::NullArgument);
-        return false;
-    }
-    */
-
     //    //Resets and creates state tracking for this graph
     //    if (!flow_job_create_state(c,job, *g)){
     //        FLOW_error_return(c);
     //    }
 
-    /*FIXME
-    if (!flow_graph_walk_dependency_wise(c, job, graph_ref, node_visitor_execute, NULL, NULL)) {
-        FLOW_error_return(c);
+    // Dispatch ready branches under a bounded memory budget, backfilling the leftover budget
+    // with whatever smaller nodes are ready when a large one doesn't fit (see
+    // `scheduler::plan_dispatch` for what a reservation does and doesn't do). Every node in a batch is independent of every other -
+    // `plan_dispatch` only ever returns nodes whose parents have already reached
+    // `NodeStage::Executed`, and since the graph is acyclic none of them can be an ancestor of
+    // another - so the batch is handed to a bounded worker pool and actually runs concurrently,
+    // one thread per dispatched node, joined before the next batch is planned.
+    let config = unsafe {
+        scheduler::SchedulerConfig {
+            max_concurrency: (*job).max_concurrency as usize,
+            memory_budget_bytes: (*job).memory_budget_bytes,
+            reservation_depth: (*job).reservation_depth as usize,
+        }
+    };
+    let pool = unsafe { (*job).buffer_pool.clone() };
+    let mut running: HashSet<daggy::NodeIndex<u32>> = HashSet::new();
+    let mut in_flight_bytes: u64 = 0;
+
+    loop {
+        // Checked once per dispatched batch - the natural node-visitor boundary here, since a
+        // batch is the largest unit of work this loop commits to before it could still back out
+        // cleanly. `imageflow_job_send_json_async`'s cancel token reaches us transitively via
+        // `Context::is_cancelled` (set by `Context::set_cancel_token`), so a cancellation
+        // requested mid-flight actually interrupts execution instead of letting it run to
+        // completion.
+        if unsafe { (*c).is_cancelled() } {
+            unsafe {
+                (*c).outward_error_mut().try_set_error(nerror!(ErrorKind::OperationCanceled,
+                    "Job execution was cancelled."));
+            }
+            error_return!(c);
+            return false;
+        }
+        let batch = scheduler::plan_dispatch(graph_ref, &config, in_flight_bytes, &running);
+        if batch.is_empty() {
+            break;
+        }
+        for dispatch in &batch {
+            running.insert(dispatch.node);
+        }
+
+        // Every thread below only ever dereferences `graph_ptr` as `&Graph` (never `&mut`), so
+        // no two threads ever hold a `&mut` into the same `Dag` allocation at once - the actual
+        // `NodeData` mutation for every node in `batch` happens below, one node at a time, only
+        // after every thread here has already been joined.
+        let graph_ptr = SendConstPtr(&*graph_ref as *const Graph);
+        let context_ptr = SendPtr(c);
+        let job_ptr = SendPtr(job);
+
+        let handles: Vec<thread::JoinHandle<Result<NodeExecutionOutcome, ()>>> = batch.iter().map(|dispatch| {
+            let node = dispatch.node;
+            let pool = pool.clone();
+            let graph_ptr = graph_ptr;
+            let context_ptr = context_ptr;
+            let job_ptr = job_ptr;
+            thread::spawn(move || {
+                let g = unsafe { &*graph_ptr.0 };
+                execute_node_with_cache(context_ptr.0, job_ptr.0, g, node, &pool)
+            })
+        }).collect();
+
+        let mut failed = false;
+        let mut outcomes: Vec<(daggy::NodeIndex<u32>, NodeExecutionOutcome)> = Vec::with_capacity(batch.len());
+        for (dispatch, handle) in batch.iter().zip(handles) {
+            match handle.join() {
+                Ok(Ok(outcome)) => outcomes.push((dispatch.node, outcome)),
+                _ => failed = true,
+            }
+        }
+        for (node, outcome) in &outcomes {
+            apply_node_execution_outcome(graph_ref, *node, outcome);
+        }
+        if failed {
+            error_return!(c);
+            return false;
+        }
+
+        for dispatch in &batch {
+            running.remove(&dispatch.node);
+            scheduler::commit_reservation(graph_ref, dispatch.node, dispatch.reserved_bytes, &mut in_flight_bytes);
+            scheduler::release_consumed_inputs(graph_ref, dispatch.node, &mut in_flight_bytes, &pool);
+        }
+    }
+    true
+}
+
+/// Lets a raw pointer cross a `thread::spawn` closure boundary - used only where the caller has
+/// already established the pointee is safe to touch from multiple threads at once (see the
+/// comment at `job_execute_where_certain`'s dispatch loop).
+#[derive(Clone, Copy)]
+struct SendPtr<T>(*mut T);
+unsafe impl<T> Send for SendPtr<T> {}
+
+/// Like `SendPtr`, but for a pointer that every thread only ever dereferences as `&T` - used for
+/// `graph_ref` in `job_execute_where_certain` so the type system itself rules out any thread
+/// reconstituting a `&mut Graph` from it.
+#[derive(Clone, Copy)]
+struct SendConstPtr<T>(*const T);
+unsafe impl<T> Send for SendConstPtr<T> {}
+
+/// Sums `NodeCost::peak_bytes` across every node, so callers can size a `memory_budget_bytes`
+/// for future runs of a similar graph off what this one actually used.
+pub fn job_peak_memory_bytes(graph_ref: &Graph) -> u64 {
+    graph_ref.raw_nodes().iter().map(|node| node.weight.cost.peak_bytes).sum()
+}
+
+/// Fill color for a node in the rendered graph, by `NodeStage` - lets an animation of
+/// consecutive versions visually trace a node's progress New -> OutboundDimensionsKnown ->
+/// Flattened -> Optimized -> Executed as the engine rewrites and schedules the graph.
+fn stage_fill_color(stage: NodeStage) -> &'static str {
+    if stage == NodeStage::Executed {
+        "gray"
+    } else if stage == NodeStage::Optimized {
+        "lightgreen"
+    } else if stage == NodeStage::Flattened {
+        "lightblue"
+    } else if stage == NodeStage::ReadyForOptimize || stage == NodeStage::ReadyForPreOptimizeFlatten ||
+              stage == NodeStage::ReadyForPostOptimizeFlatten || stage == NodeStage::ReadyForExecution {
+        "lightyellow"
+    } else {
+        "white"
     }
-    */
-    return true;
 }
 
 pub fn job_render_graph_to_png(c: *mut Context, job: *mut Job, g: &mut Graph, graph_version: int32_t) -> bool
 {
     let filename = format!("job_{}_graph_version_{}.dot", unsafe { (*job).debug_job_id }, graph_version);
     let mut file = File::create(&filename).unwrap();
-    file.write_fmt(format_args!("{:?}", Dot::new(g.graph())));
-    Command::new("dot").arg("-Tpng").arg("-Gsize=11,16\\!").arg("-Gdpi=150").arg("-O").arg(filename)
+    let dot = Dot::with_attr_getters(g.graph(), &[],
+        &|_, _| String::new(),
+        &|_, (_, node)| format!("style=filled,fillcolor={}", stage_fill_color(node.stage)));
+    file.write_fmt(format_args!("{:?}", dot));
+    Command::new("dot").arg("-Tpng").arg("-Gsize=11,16\\!").arg("-Gdpi=150").arg("-O").arg(&filename)
+                       .spawn().expect("dot command failed");
+    Command::new("dot").arg("-Tsvg").arg("-O").arg(&filename)
                        .spawn().expect("dot command failed");
     return true;
 }
 
+/// Container formats `job_stitch_graph_versions_into_animation` can produce from the ordered
+/// per-version PNGs `job_notify_graph_changed` already wrote out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphAnimationFormat {
+    Gif,
+    Apng,
+}
+
+/// Stitches every recorded `job_%d_graph_version_%d.dot.png` for this job, in version order,
+/// into a single animated image - a frame per distinct graph version `job_notify_graph_changed`
+/// kept, showing the flatten/optimize/execute loop rewrite the graph over time. Requires
+/// `job.render_graph_versions` to have been set (otherwise there are no per-version PNGs to
+/// stitch) and an external `convert`/`apngasm` binary on `PATH`.
+pub fn job_stitch_graph_versions_into_animation(job: *mut Job, format: GraphAnimationFormat) -> bool {
+    let debug_job_id = unsafe { (*job).debug_job_id };
+    let recorded_versions = unsafe { (*job).recorded_graph_snapshots.len() };
+    let frame_paths: Vec<String> = (0..recorded_versions)
+        .map(|v| format!("job_{}_graph_version_{}.dot.png", debug_job_id, v))
+        .collect();
+    if frame_paths.is_empty() {
+        return true;
+    }
+    match format {
+        GraphAnimationFormat::Gif => {
+            let output = format!("job_{}_graph_versions.gif", debug_job_id);
+            let mut cmd = Command::new("convert");
+            cmd.arg("-delay").arg("75").arg("-loop").arg("0");
+            for path in &frame_paths {
+                cmd.arg(path);
+            }
+            cmd.arg(output).spawn().expect("convert command failed");
+        }
+        GraphAnimationFormat::Apng => {
+            let output = format!("job_{}_graph_versions.apng", debug_job_id);
+            let mut cmd = Command::new("apngasm");
+            cmd.arg(output);
+            for path in &frame_paths {
+                cmd.arg(path).arg("75").arg("100");
+            }
+            cmd.spawn().expect("apngasm command failed");
+        }
+    }
+    return true;
+}
+
 pub fn node_visitor_optimize(c: *mut Context, job: *mut Job, graph_ref: &mut Graph, node_id: int32_t,
-                                  quit:*mut bool, skip_outbound_paths: *mut bool, custom_data: *mut c_void) -> bool
+                                  control: &mut VisitControl) -> Result<bool, ()>
 {
-    graph_ref.node_weight_mut(node_index(node_id as usize)).map(|node| {
+    Ok(graph_ref.node_weight_mut(node_index(node_id as usize)).map(|node| {
         // Implement optimizations
         if node.stage == NodeStage::ReadyForOptimize {
             //FIXME: should we implement AND on NodeStage?
             //node.stage |= NodeStage::Optimized;
             node.stage = NodeStage::Optimized;
+            true
+        } else {
+            false
         }
-        true
-    }).unwrap_or(false)
+    }).unwrap_or(false))
 }
 
 pub fn flow_node_has_dimensions(c: *mut Context, g: &Graph, node_id: int32_t) -> bool
@@ -382,12 +497,13 @@ pub fn flow_job_populate_dimensions_for_node(c: *mut Context, job: *mut Job, g:
 
 pub fn flow_job_force_populate_dimensions(c: *mut Context, job: *mut Job, graph_ref: &mut Graph) -> bool
 {
-    //FIXME: reimplement
     // TODO: would be good to verify graph is acyclic.
-    //if (!flow_graph_walk(c, job, graph_ref, node_visitor_dimensions, NULL, (void *)true)) {
-    //    FLOW_error_return(c);
-    //}
-    return true;
+    match walk_dependency_wise_to_fixpoint(graph_ref, |g, node, control| {
+        node_visitor_dimensions(c, job, g, node, control, true)
+    }) {
+        Ok(()) => true,
+        Err(()) => { error_return!(c); false }
+    }
 }
 
 pub fn flow_node_populate_dimensions(c: *mut Context, g: &mut Graph, node_id: int32_t, force_estimate: bool) -> bool
@@ -414,129 +530,229 @@ pub fn flow_node_populate_dimensions(c: *mut Context, g: &mut Graph, node_id: in
     return true;
 }
 
-/* FIXME
-static bool node_visitor_post_optimize_flatten(flow_c * c, struct flow_job * job, struct flow_graph ** graph_ref,
-                                               int32_t node_id, bool * quit, bool * skip_outbound_paths,
-                                               void * custom_data)
+pub fn flow_node_pre_optimize_flatten(c: *mut Context, g: &mut Graph, node_id: int32_t) -> bool
 {
+    // FIXME: reimplement per-node-type flattening (e.g. expanding a resize+crop shorthand
+    // into its constituent primitive nodes) once `nodes` exposes per-type definitions.
+    return true;
+}
 
-    if (!flow_node_update_state(c, *graph_ref, node_id)) {
-        FLOW_error_return(c);
-    }
-    struct flow_node * n = &(*graph_ref)->nodes[node_id];
-
-    // If input nodes are populated
-    if (n->state == flow_node_state_ReadyForPostOptimizeFlatten) {
-        if (!flow_node_post_optimize_flatten(c, graph_ref, node_id)) {
-            FLOW_error_return(c);
-        }
-        if (!flow_graph_validate(c, *graph_ref)) {
-            FLOW_error_return(c);
-        }
-        *quit = true;
-        *((bool *)custom_data) = true;
-    } else if ((n->state & flow_node_state_InputDimensionsKnown) == 0) {
-        // we can't flatten past missing dimensions
-        *skip_outbound_paths = true;
-    }
+pub fn flow_node_post_optimize_flatten(c: *mut Context, g: &mut Graph, node_id: int32_t) -> bool
+{
+    // FIXME: reimplement once `nodes` exposes per-type definitions.
     return true;
 }
 
-static bool node_visitor_dimensions(flow_c * c, struct flow_job * job, struct flow_graph ** graph_ref, int32_t node_id,
-                                    bool * quit, bool * skip_outbound_paths, void * custom_data)
+pub fn flow_graph_validate(c: *mut Context, g: &Graph) -> bool
 {
+    // FIXME: do we need to validate if daggy ensures the graph is valid?
+    return true;
+}
 
-    struct flow_node * n = &(*graph_ref)->nodes[node_id];
+/// Takes `g` by shared reference rather than `&mut` so `execute_node_with_cache` can run this
+/// concurrently for every node in a dispatch batch - each node's own per-type pixel storage
+/// (not modeled as part of `NodeData` here) is what an eventual `nodes`-backed implementation
+/// would actually write to, not anything reachable through `g`.
+pub fn flow_node_execute(c: *mut Context, job: *mut Job, g: &Graph, node_id: int32_t) -> bool
+{
+    // FIXME: reimplement once `nodes` exposes per-type execute callbacks.
+    return true;
+}
 
-    int32_t outbound_edges = flow_graph_get_edge_count(c, *graph_ref, node_id, false, flow_edgetype_null, false, true);
-    if (outbound_edges == 0) {
-        return true; // Endpoint node - no need.
+fn node_visitor_flatten(c: *mut Context, g: &mut Graph, node: daggy::NodeIndex<u32>,
+                         control: &mut VisitControl) -> Result<bool, ()>
+{
+    let node_id = node.index() as int32_t;
+    if !flow_node_inputs_have_dimensions(c, g, node_id) {
+        // we can't flatten past missing dimensions
+        control.skip_outbound_paths = true;
+        return Ok(false);
     }
-    if (!flow_node_has_dimensions(c, *graph_ref, node_id)) {
-        if (!flow_node_update_state(c, *graph_ref, node_id)) {
-            FLOW_error_return(c);
+    let stage = g.node_weight(node).map(|n| n.stage);
+    if stage == Some(NodeStage::ReadyForPreOptimizeFlatten) {
+        if !flow_node_pre_optimize_flatten(c, g, node_id) {
+            error_return!(c);
+            return Err(());
         }
+        g.node_weight_mut(node).map(|n| n.stage = NodeStage::Flattened);
+        control.quit = true;
+        return Ok(true);
+    }
+    Ok(false)
+}
 
-        // If input nodes are populated
-        if ((n->state & flow_node_state_InputDimensionsKnown) > 0) {
-            if (!flow_job_populate_dimensions_for_node(c, job, *graph_ref, node_id, (bool)custom_data)) {
-                FLOW_error_return(c);
-            }
+fn node_visitor_post_optimize_flatten(c: *mut Context, job: *mut Job, g: &mut Graph, node: daggy::NodeIndex<u32>,
+                                       control: &mut VisitControl) -> Result<bool, ()>
+{
+    let node_id = node.index() as int32_t;
+    if !flow_node_inputs_have_dimensions(c, g, node_id) {
+        // we can't flatten past missing dimensions
+        control.skip_outbound_paths = true;
+        return Ok(false);
+    }
+    let stage = g.node_weight(node).map(|n| n.stage);
+    if stage == Some(NodeStage::ReadyForPostOptimizeFlatten) {
+        if !flow_node_post_optimize_flatten(c, g, node_id) {
+            error_return!(c);
+            return Err(());
         }
-        if (!flow_node_has_dimensions(c, *graph_ref, node_id)) {
-            // We couldn't populate this edge, so we sure can't populate others in this direction.
-            // Stop this branch of recursion
-            *skip_outbound_paths = true;
-        } else {
-            flow_job_notify_graph_changed(c, job, *graph_ref);
+        if !flow_graph_validate(c, g) {
+            error_return!(c);
+            return Err(());
         }
+        g.node_weight_mut(node).map(|n| n.stage = NodeStage::Flattened);
+        control.quit = true;
+        return Ok(true);
     }
-    return true;
+    Ok(false)
 }
 
+/// Computes the Merkle hash over the subgraph feeding `node_id`: its own `type_digest_seed`
+/// folded with each non-`EdgeKind::None` parent's cache digest, in dependency-wise parent order.
+/// Since nodes are only visited once every such parent is `Executed`, every parent here already
+/// carries a digest from this run (or from a prior cache hit) - changing any upstream parameter
+/// changes some parent's digest, which changes this one automatically.
+pub fn flow_node_compute_cache_digest(c: *mut Context, g: &Graph, node_id: int32_t) -> NodeDigest {
+    let node = node_index(node_id as usize);
+    let mut digest = g.node_weight(node).map(|n| n.type_digest_seed).unwrap_or_else(|| NodeDigest::of(&node_id));
+    for (edge_index, parent) in g.parents(node).iter(g) {
+        if *g.edge_weight(edge_index).unwrap() != EdgeKind::None {
+            if let Some(parent_digest) = g.node_weight(parent).and_then(|n| n.cache_digest) {
+                digest = digest.combine(parent_digest);
+            }
+        }
+    }
+    digest
+}
 
+/// Exports the rendered bitmap bytes for an already-`Executed` node, for insertion into a
+/// `ResultCache`. `None` means "nothing worth caching" (e.g. the node type doesn't produce a
+/// standalone bitmap) rather than an error.
+pub fn flow_node_export_output_bitmap(c: *mut Context, job: *mut Job, g: &Graph, node_id: int32_t) -> Option<Vec<u8>> {
+    // FIXME: reimplement once `nodes` exposes the rendered bitmap for a node.
+    None
+}
 
-//FIXME: can be deleted
-static bool flow_job_node_is_executed(flow_c * c, struct flow_job * job, struct flow_graph * g, int32_t node_id)
-{
-    return (g->nodes[node_id].stage & flow_node_state_Executed) > 0;
+/// Loads previously-cached bitmap bytes into `node_id`'s output in place of executing it.
+pub fn flow_node_load_output_bitmap(c: *mut Context, job: *mut Job, g: &Graph, node_id: int32_t, bitmap: &[u8]) -> bool {
+    // FIXME: reimplement once `nodes` exposes a way to install a node's output bitmap.
+    true
 }
-*/
 
+/// What `execute_node_with_cache` learned about a node, carried back across a `thread::spawn`
+/// boundary so the calling thread - and only the calling thread - can apply it to `NodeData`
+/// once every worker in the batch has been joined. Keeping worker threads to a shared `&Graph`
+/// and funneling every mutation through this struct means no two threads ever hold a `&mut`
+/// into the same `Dag` at once, unlike reconstituting independent `&mut Graph`s from a raw
+/// pointer (which is UB regardless of whether the touched nodes are disjoint).
+struct NodeExecutionOutcome {
+    digest: NodeDigest,
+    wall_ticks: u32,
+    peak_bytes: u64,
+    reused_bytes: u64,
+    pooled_buffer: Option<(usize, usize)>,
+}
 
-/*FIXME
-static bool node_visitor_execute(flow_c * c, struct flow_job * job, struct flow_graph ** graph_ref, int32_t node_id,
-                                 bool * quit, bool * skip_outbound_paths, void * custom_data)
+/// Runs (or cache-loads) one already-`ReadyForExecution` node without mutating `g` - safe to call
+/// concurrently for every node in a dispatch batch, since each call only ever takes a shared
+/// reference. The scheduler's job; this just does the cache-lookup/execute/cache-insert dance for
+/// a single node and hands back what it learned; `apply_node_execution_outcome` is what actually
+/// marks the node `Executed`.
+fn execute_node_with_cache(c: *mut Context, job: *mut Job, g: &Graph, node: daggy::NodeIndex<u32>,
+                            pool: &pool::BufferPool) -> Result<NodeExecutionOutcome, ()>
 {
+    let node_id = node.index() as int32_t;
+    let digest = flow_node_compute_cache_digest(c, g, node_id);
+    let cache = unsafe { (*job).result_cache.clone() };
+    if let Some(bitmap) = cache.as_ref().and_then(|rc| rc.get(digest)) {
+        if !flow_node_load_output_bitmap(c, job, g, node_id, &bitmap) {
+            return Err(());
+        }
+        Ok(NodeExecutionOutcome {
+            digest: digest,
+            wall_ticks: 0,
+            peak_bytes: 0,
+            reused_bytes: 0,
+            pooled_buffer: None,
+        })
+    } else {
+        // Hand the node its output buffer from the pool rather than a raw allocation, so a
+        // same-sized buffer freed by an earlier node (see `scheduler::release_consumed_inputs`)
+        // gets reused instead of allocating fresh.
+        let size = scheduler::estimated_output_bytes(g, node) as usize;
+        let excess = match pool.alloc_zeroed(size) {
+            Some(excess) => excess,
+            None => {
+                unsafe {
+                    (*c).outward_error_mut().try_set_error(nerror!(ErrorKind::AllocationFailed,
+                        "Failed to allocate a {} byte output buffer for node {}", size, node_id));
+                }
+                return Err(());
+            }
+        };
 
-    if (!flow_node_update_state(c, *graph_ref, node_id)) {
-        FLOW_error_return(c);
-    }
-    struct flow_node * n = &(*graph_ref)->nodes[node_id];
+        let now = time::precise_time_ns();
+        if !flow_node_execute(c, job, g, node_id) {
+            pool.dealloc(excess.ptr, excess.capacity);
+            return Err(());
+        }
+        let elapsed = (time::precise_time_ns() - now) as u32;
 
-    if (!flow_job_node_is_executed(c, job, *graph_ref, node_id) && n->state == flow_node_state_ReadyForExecution) {
-        uint64_t now = flow_get_high_precision_ticks();
-        if (!flow_node_execute(c, job, *graph_ref, node_id)) {
-            FLOW_error_return(c);
-        } else {
-            (*graph_ref)->nodes[node_id].ticks_elapsed += (int32_t)(flow_get_high_precision_ticks() - now);
-            n->state = (flow_node_state)(n->state | flow_node_state_Executed);
-            flow_job_notify_node_complete(c, job, *graph_ref, node_id);
+        if let Some(ref rc) = cache {
+            if let Some(bitmap) = flow_node_export_output_bitmap(c, job, g, node_id) {
+                rc.put(digest, bitmap);
+            }
         }
+        Ok(NodeExecutionOutcome {
+            digest: digest,
+            wall_ticks: elapsed,
+            peak_bytes: excess.capacity as u64,
+            reused_bytes: if excess.reused { excess.capacity as u64 } else { 0 },
+            pooled_buffer: Some((excess.ptr as usize, excess.capacity)),
+        })
     }
-    if (!flow_job_node_is_executed(c, job, *graph_ref, node_id)) {
-        // If we couldn't complete this node yet, end this branch.
-        *skip_outbound_paths = true;
-    } else {
-        flow_job_notify_graph_changed(c, job, *graph_ref);
-    }
-    return true;
 }
 
-// if no hits, search forward
-
+/// Applies a `NodeExecutionOutcome` to `NodeData` - the only place `job_execute_where_certain`'s
+/// dispatch loop touches `&mut Graph`, and only ever on the calling thread after every worker in
+/// the batch has already been joined.
+fn apply_node_execution_outcome(g: &mut Graph, node: daggy::NodeIndex<u32>, outcome: &NodeExecutionOutcome) {
+    g.node_weight_mut(node).map(|n| {
+        if let Some(pooled_buffer) = outcome.pooled_buffer {
+            n.cost.wall_ticks += outcome.wall_ticks;
+            n.cost.peak_bytes = n.cost.peak_bytes.max(outcome.peak_bytes);
+            n.cost.reused_bytes += outcome.reused_bytes;
+            n.pooled_buffer = Some(pooled_buffer);
+        }
+        n.cache_digest = Some(outcome.digest);
+        n.stage = NodeStage::Executed;
+    });
+}
 
-static bool node_visitor_flatten(flow_c * c, struct flow_job * job, struct flow_graph ** graph_ref, int32_t node_id,
-                                 bool * quit, bool * skip_outbound_paths, void * custom_data)
+fn node_visitor_dimensions(c: *mut Context, job: *mut Job, g: &mut Graph, node: daggy::NodeIndex<u32>,
+                            control: &mut VisitControl, force_estimate: bool) -> Result<bool, ()>
 {
-
-    if (!flow_node_update_state(c, *graph_ref, node_id)) {
-        FLOW_error_return(c);
+    let node_id = node.index() as int32_t;
+    if g.children(node).iter(g).next().is_none() {
+        return Ok(false); // Endpoint node - no need.
     }
-    struct flow_node * n = &(*graph_ref)->nodes[node_id];
-
-    // If input nodes are populated
-    if (n->state == flow_node_state_ReadyForPreOptimizeFlatten) {
-        if (!flow_node_pre_optimize_flatten(c, graph_ref, node_id)) {
-            FLOW_error_return(c);
-        }
-        *quit = true;
-        *((bool *)custom_data) = true;
-    } else if ((n->state & flow_node_state_InputDimensionsKnown) == 0) {
-        // we can't flatten past missing dimensions
-        *skip_outbound_paths = true;
+    if flow_node_has_dimensions(c, g, node_id) {
+        return Ok(false);
+    }
+    if !flow_node_inputs_have_dimensions(c, g, node_id) {
+        // We couldn't populate this edge, so we sure can't populate others in this direction.
+        // Stop this branch of recursion
+        control.skip_outbound_paths = true;
+        return Ok(false);
+    }
+    if !flow_job_populate_dimensions_for_node(c, job, g, node_id, force_estimate) {
+        error_return!(c);
+        return Err(());
+    }
+    if !flow_node_has_dimensions(c, g, node_id) {
+        control.skip_outbound_paths = true;
+        Ok(false)
+    } else {
+        Ok(true)
     }
-    return true;
 }
-
-*/
@@ -0,0 +1,104 @@
+use libc;
+use std::collections::HashMap;
+use std::ptr;
+use std::sync::Mutex;
+
+/// A buffer handed back by the pool: the pointer, its actual (bucket-rounded) capacity, and
+/// whether it came from the free list rather than a fresh heap allocation - the three things a
+/// caller needs to both use the buffer and later feed it back into `NodeCost` accounting.
+pub struct Excess {
+    pub ptr: *mut u8,
+    pub capacity: usize,
+    pub reused: bool,
+}
+
+fn bucket_for(size: usize) -> usize {
+    if size == 0 { 1 } else { size.next_power_of_two() }
+}
+
+/// Hands out bitmap frame storage during execution in place of raw `malloc`/`calloc`, recycling
+/// freed buffers through a size-bucketed free list: a node's buffer returns here once all of its
+/// outbound consumers have read it (see `scheduler::release_consumed_inputs`), so a long pipeline
+/// of same-sized frames reuses a handful of buffers instead of allocating one per node.
+pub struct BufferPool {
+    free_lists: Mutex<HashMap<usize, Vec<usize>>>,
+}
+
+unsafe impl Send for BufferPool {}
+unsafe impl Sync for BufferPool {}
+
+impl BufferPool {
+    pub fn new() -> BufferPool {
+        BufferPool { free_lists: Mutex::new(HashMap::new()) }
+    }
+
+    fn take_from_free_list(&self, bucket: usize) -> Option<*mut u8> {
+        let mut lists = self.free_lists.lock().unwrap();
+        lists.get_mut(&bucket).and_then(|list| list.pop()).map(|p| p as *mut u8)
+    }
+
+    /// Allocates at least `size` bytes, preferring a recycled buffer from the free list.
+    /// Returns `None` (propagating the host allocator's failure rather than handing back a
+    /// null pointer) if the underlying `malloc` call fails.
+    pub fn alloc(&self, size: usize) -> Option<Excess> {
+        let bucket = bucket_for(size);
+        if let Some(ptr) = self.take_from_free_list(bucket) {
+            return Some(Excess { ptr: ptr, capacity: bucket, reused: true });
+        }
+        let ptr = unsafe { libc::malloc(bucket as libc::size_t) as *mut u8 };
+        if ptr.is_null() {
+            return None;
+        }
+        Some(Excess { ptr: ptr, capacity: bucket, reused: false })
+    }
+
+    /// Like `alloc`, but the returned buffer is zero-initialized - recycled buffers are
+    /// zeroed in place rather than handed back dirty. `None` on allocation failure.
+    pub fn alloc_zeroed(&self, size: usize) -> Option<Excess> {
+        let bucket = bucket_for(size);
+        if let Some(ptr) = self.take_from_free_list(bucket) {
+            unsafe { ptr::write_bytes(ptr, 0, bucket); }
+            return Some(Excess { ptr: ptr, capacity: bucket, reused: true });
+        }
+        let ptr = unsafe { libc::calloc(1, bucket as libc::size_t) as *mut u8 };
+        if ptr.is_null() {
+            return None;
+        }
+        Some(Excess { ptr: ptr, capacity: bucket, reused: false })
+    }
+
+    /// Returns any capacity beyond what was requested, rounded up to this pool's bucket size -
+    /// for callers that can make use of over-provisioned space (e.g. a node whose real output
+    /// came in smaller than its `FrameEstimate`) without a follow-up `realloc` down. `None` on
+    /// allocation failure.
+    pub fn alloc_excess(&self, size: usize) -> Option<Excess> {
+        self.alloc(size)
+    }
+
+    /// Resizes a previously-allocated buffer. Reuses it in place when the new size rounds to
+    /// the same bucket; otherwise allocates a new bucket and frees the old pointer via `libc`
+    /// (not the free list - `old_capacity` no longer describes any buffer the pool is tracking).
+    /// `None` on allocation failure - `ptr` is left untouched (still valid at `old_capacity`),
+    /// matching `libc::realloc`'s own contract on failure.
+    pub fn realloc(&self, ptr: *mut u8, old_capacity: usize, new_size: usize) -> Option<Excess> {
+        let new_bucket = bucket_for(new_size);
+        if new_bucket == old_capacity {
+            return Some(Excess { ptr: ptr, capacity: old_capacity, reused: true });
+        }
+        let new_ptr = unsafe { libc::realloc(ptr as *mut libc::c_void, new_bucket as libc::size_t) as *mut u8 };
+        if new_ptr.is_null() {
+            return None;
+        }
+        Some(Excess { ptr: new_ptr, capacity: new_bucket, reused: false })
+    }
+
+    /// Returns `ptr` (of the given `capacity`) to the size-bucketed free list instead of
+    /// freeing it immediately, so the next node needing a similarly-sized buffer can reuse it.
+    pub fn dealloc(&self, ptr: *mut u8, capacity: usize) {
+        if ptr.is_null() {
+            return;
+        }
+        let mut lists = self.free_lists.lock().unwrap();
+        lists.entry(capacity).or_insert_with(Vec::new).push(ptr as usize);
+    }
+}
@@ -0,0 +1,129 @@
+use daggy;
+use daggy::walker::Walker;
+use petgraph::graph::node_index;
+use std::collections::HashSet;
+use super::definitions::*;
+use super::graph::Graph;
+use super::pool::BufferPool;
+
+/// `max_concurrency`/`memory_budget_bytes`/`reservation_depth` as read off `Job` for one
+/// `job_execute_where_certain` run - copied in rather than re-reading `*job` on every
+/// `plan_dispatch` call, since those fields don't change mid-execution.
+pub struct SchedulerConfig {
+    pub max_concurrency: usize,
+    pub memory_budget_bytes: u64,
+    pub reservation_depth: usize,
+}
+
+/// One node the scheduler has decided to run this round, and the output bytes reserved
+/// against the memory budget for it.
+pub struct Dispatch {
+    pub node: daggy::NodeIndex<u32>,
+    pub reserved_bytes: u64,
+}
+
+pub fn estimated_output_bytes(g: &Graph, node: daggy::NodeIndex<u32>) -> u64 {
+    match g.node_weight(node) {
+        Some(n) => match n.frame_est {
+            FrameEstimate::Some(ref est) => est.estimated_byte_count(),
+            _ => 0,
+        },
+        None => 0,
+    }
+}
+
+fn node_ready_for_execution(g: &mut Graph, node: daggy::NodeIndex<u32>) -> bool {
+    let is_ready_stage = g.node_weight(node).map(|n| n.stage == NodeStage::ReadyForExecution).unwrap_or(false);
+    is_ready_stage && g.parents(node).iter(g).all(|(edge, parent)| {
+        *g.edge_weight(edge).unwrap() == EdgeKind::None ||
+        g.node_weight(parent).map(|p| p.stage == NodeStage::Executed).unwrap_or(false)
+    })
+}
+
+/// Picks the next batch of ready nodes to dispatch: walks the ready-set in priority (node
+/// insertion) order, dispatching every node whose estimated output fits the remaining budget.
+/// A ready node that doesn't fit is passed over, and the scan continues past it to *backfill*
+/// smaller nodes into the leftover budget. Up to `reservation_depth` such nodes per round are
+/// counted as reservations, but a reservation holds no budget and does not stop the scan, so
+/// a large node keeps being passed over for as long as smaller ready nodes fill the budget
+/// ahead of it. Nodes already in
+/// `running` are skipped - they're still occupying a concurrency slot and/or their reservation.
+pub fn plan_dispatch(g: &mut Graph, config: &SchedulerConfig, in_flight_bytes: u64,
+                      running: &HashSet<daggy::NodeIndex<u32>>) -> Vec<Dispatch> {
+    let mut remaining_budget = config.memory_budget_bytes.saturating_sub(in_flight_bytes);
+    let mut dispatch = Vec::new();
+    let mut reserved_slots = 0usize;
+
+    let candidates: Vec<daggy::NodeIndex<u32>> = g.raw_nodes().iter().enumerate()
+        .map(|(i, _)| node_index(i))
+        .filter(|&node| !running.contains(&node) && node_ready_for_execution(g, node))
+        .collect();
+
+    for node in candidates {
+        if running.len() + dispatch.len() >= config.max_concurrency {
+            break;
+        }
+        let bytes = estimated_output_bytes(g, node);
+        if bytes <= remaining_budget {
+            remaining_budget -= bytes;
+            dispatch.push(Dispatch { node: node, reserved_bytes: bytes });
+        } else if reserved_slots < config.reservation_depth {
+            // Count this node as reserved (it isn't dispatched, and no budget is held for it),
+            // then keep scanning for smaller ready nodes to backfill the budget this round.
+            reserved_slots += 1;
+        }
+    }
+
+    dispatch
+}
+
+/// Charges `reserved_bytes` against the budget for `node` and records how many outbound
+/// consumers still need to read it. A node with no consumers (a graph output) releases its
+/// own reservation immediately - nothing will ever decrement it for us.
+pub fn commit_reservation(g: &mut Graph, node: daggy::NodeIndex<u32>, reserved_bytes: u64, in_flight_bytes: &mut u64) {
+    *in_flight_bytes += reserved_bytes;
+    let pending_consumers = g.children(node).iter(g)
+        .filter(|&(edge, _)| *g.edge_weight(edge).unwrap() != EdgeKind::None)
+        .count() as u32;
+    g.node_weight_mut(node).map(|n| {
+        n.reserved_bytes = reserved_bytes;
+        n.pending_consumers = pending_consumers;
+    });
+    if pending_consumers == 0 {
+        *in_flight_bytes = in_flight_bytes.saturating_sub(reserved_bytes);
+        g.node_weight_mut(node).map(|n| n.reserved_bytes = 0);
+    }
+}
+
+/// `node` has just consumed its inputs - decrements each parent's `pending_consumers`, and for
+/// any parent that just dropped to zero, releases its reserved bytes back into the budget and
+/// returns its pooled buffer (if any) to `pool`'s free list for the next node to reuse.
+pub fn release_consumed_inputs(g: &mut Graph, node: daggy::NodeIndex<u32>, in_flight_bytes: &mut u64,
+                                pool: &BufferPool) {
+    let parents: Vec<daggy::NodeIndex<u32>> = g.parents(node).iter(g)
+        .filter(|&(edge, _)| *g.edge_weight(edge).unwrap() != EdgeKind::None)
+        .map(|(_, parent)| parent)
+        .collect();
+
+    for parent in parents {
+        let released = g.node_weight_mut(parent).and_then(|p| {
+            if p.pending_consumers > 0 {
+                p.pending_consumers -= 1;
+            }
+            if p.pending_consumers == 0 && (p.reserved_bytes > 0 || p.pooled_buffer.is_some()) {
+                let bytes = p.reserved_bytes;
+                let buffer = p.pooled_buffer.take();
+                p.reserved_bytes = 0;
+                Some((bytes, buffer))
+            } else {
+                None
+            }
+        });
+        if let Some((bytes, buffer)) = released {
+            *in_flight_bytes = in_flight_bytes.saturating_sub(bytes);
+            if let Some((ptr, capacity)) = buffer {
+                pool.dealloc(ptr as *mut u8, capacity);
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use serde::{Serialize, Deserialize};
+use daggy::walker::Walker;
+use petgraph::graph::node_index;
+use super::cache::NodeDigest;
+use super::definitions::*;
+use super::graph::Graph;
+
+/// Identifies this file format to anything reading it off disk (a malformed/foreign blob won't
+/// happen to start with this) - mirrors how network-protocol handshakes lead with a magic value
+/// before negotiating `distributed_db_version`/`p2p_version`.
+pub const GRAPH_SNAPSHOT_MAGIC: &'static str = "FLOWGRAPH";
+
+/// The envelope format itself - field layout, compression, etc. Bump when the envelope
+/// changes shape, independently of the per-node schema below.
+pub const CURRENT_FORMAT_VERSION: u16 = 1;
+
+/// The schema of `NodeSnapshot`/`EdgeSnapshot`. Bump when a node-level field is added, removed,
+/// or changes meaning; `migrate_node_schema` below must grow a case to bring anything older
+/// forward to this version.
+pub const CURRENT_NODE_SCHEMA_VERSION: u16 = 1;
+
+/// Oldest node-schema version `migrate_node_schema` still knows how to bring forward.
+pub const MIN_SUPPORTED_NODE_SCHEMA_VERSION: u16 = 1;
+
+/// Best-effort mirror of `NodeStage` - only the variants this module currently drives through
+/// `job_execute`'s passes are named; anything else round-trips as `Unknown` rather than failing
+/// the whole snapshot. Exhaustive coverage needs `definitions::NodeStage` itself to derive
+/// `Serialize`/`Deserialize`, which isn't ours to add in this tree.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeStageSnapshot {
+    New,
+    ReadyForOptimize,
+    Optimized,
+    ReadyForPreOptimizeFlatten,
+    ReadyForPostOptimizeFlatten,
+    Flattened,
+    ReadyForExecution,
+    Executed,
+    Unknown,
+}
+
+impl NodeStageSnapshot {
+    fn from_stage(stage: NodeStage) -> NodeStageSnapshot {
+        if stage == NodeStage::New {
+            NodeStageSnapshot::New
+        } else if stage == NodeStage::ReadyForOptimize {
+            NodeStageSnapshot::ReadyForOptimize
+        } else if stage == NodeStage::Optimized {
+            NodeStageSnapshot::Optimized
+        } else if stage == NodeStage::ReadyForPreOptimizeFlatten {
+            NodeStageSnapshot::ReadyForPreOptimizeFlatten
+        } else if stage == NodeStage::ReadyForPostOptimizeFlatten {
+            NodeStageSnapshot::ReadyForPostOptimizeFlatten
+        } else if stage == NodeStage::Flattened {
+            NodeStageSnapshot::Flattened
+        } else if stage == NodeStage::ReadyForExecution {
+            NodeStageSnapshot::ReadyForExecution
+        } else if stage == NodeStage::Executed {
+            NodeStageSnapshot::Executed
+        } else {
+            NodeStageSnapshot::Unknown
+        }
+    }
+
+    /// `Unknown` restores as `New` (the conservative choice: re-run every pass for this node
+    /// rather than risk skipping work it actually still needs).
+    fn to_stage(&self) -> NodeStage {
+        match *self {
+            NodeStageSnapshot::New => NodeStage::New,
+            NodeStageSnapshot::ReadyForOptimize => NodeStage::ReadyForOptimize,
+            NodeStageSnapshot::Optimized => NodeStage::Optimized,
+            NodeStageSnapshot::ReadyForPreOptimizeFlatten => NodeStage::ReadyForPreOptimizeFlatten,
+            NodeStageSnapshot::ReadyForPostOptimizeFlatten => NodeStage::ReadyForPostOptimizeFlatten,
+            NodeStageSnapshot::Flattened => NodeStage::Flattened,
+            NodeStageSnapshot::ReadyForExecution => NodeStage::ReadyForExecution,
+            NodeStageSnapshot::Executed => NodeStage::Executed,
+            NodeStageSnapshot::Unknown => NodeStage::New,
+        }
+    }
+}
+
+/// Mirrors the `EdgeKind::None`/not-`None` distinction the rest of this module already relies
+/// on (`flow_node_inputs_have_dimensions` and friends never inspect a non-`None` edge any more
+/// closely than that).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKindSnapshot {
+    None,
+    Data,
+}
+
+impl EdgeKindSnapshot {
+    fn from_edge_kind(kind: EdgeKind) -> EdgeKindSnapshot {
+        if kind == EdgeKind::None { EdgeKindSnapshot::None } else { EdgeKindSnapshot::Data }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeCostSnapshot {
+    pub wall_ticks: u32,
+    pub peak_bytes: u64,
+    pub reused_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct NodeSnapshot {
+    pub index: u32,
+    pub stage: NodeStageSnapshot,
+    pub has_dimensions: bool,
+    pub cost: NodeCostSnapshot,
+    pub cache_digest: Option<(u64, u64)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct EdgeSnapshot {
+    pub from: u32,
+    pub to: u32,
+    pub kind: EdgeKindSnapshot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphSnapshot {
+    pub nodes: Vec<NodeSnapshot>,
+    pub edges: Vec<EdgeSnapshot>,
+}
+
+/// The on-disk/over-the-wire wrapper: a magic identifier plus the two independent version
+/// numbers callers negotiate against before trusting `graph`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphEnvelope {
+    pub magic: String,
+    pub format_version: u16,
+    pub node_schema_version: u16,
+    pub graph: GraphSnapshot,
+}
+
+/// Snapshots `g`'s current execution state - stage, cost accounting, and cache digest per node,
+/// plus every edge and its kind. Node-type-specific parameters and `FrameEstimate`'s actual
+/// dimensions aren't captured; resuming a snapshot re-applies state onto a graph already
+/// rebuilt from the same recipe (see `apply_snapshot_to_graph`), so those are expected to
+/// already be present rather than needing to round-trip here.
+pub fn snapshot_graph(g: &Graph) -> GraphEnvelope {
+    let mut nodes = Vec::new();
+    for (i, node) in g.raw_nodes().iter().enumerate() {
+        nodes.push(NodeSnapshot {
+            index: i as u32,
+            stage: NodeStageSnapshot::from_stage(node.weight.stage),
+            has_dimensions: match node.weight.frame_est { FrameEstimate::Some(_) => true, _ => false },
+            cost: NodeCostSnapshot {
+                wall_ticks: node.weight.cost.wall_ticks,
+                peak_bytes: node.weight.cost.peak_bytes,
+                reused_bytes: node.weight.cost.reused_bytes,
+            },
+            cache_digest: node.weight.cache_digest.map(|d| (d.0, d.1)),
+        });
+    }
+
+    let mut edges = Vec::new();
+    for i in 0..g.raw_nodes().len() {
+        let node = node_index(i);
+        for (edge_index, parent) in g.parents(node).iter(g) {
+            edges.push(EdgeSnapshot {
+                from: parent.index() as u32,
+                to: node.index() as u32,
+                kind: EdgeKindSnapshot::from_edge_kind(*g.edge_weight(edge_index).unwrap()),
+            });
+        }
+    }
+
+    GraphEnvelope {
+        magic: GRAPH_SNAPSHOT_MAGIC.to_string(),
+        format_version: CURRENT_FORMAT_VERSION,
+        node_schema_version: CURRENT_NODE_SCHEMA_VERSION,
+        graph: GraphSnapshot { nodes: nodes, edges: edges },
+    }
+}
+
+/// Brings a `GraphSnapshot` forward from an older `node_schema_version` to
+/// `CURRENT_NODE_SCHEMA_VERSION`, in place. Add a case here (and bump
+/// `CURRENT_NODE_SCHEMA_VERSION`) the next time a node-level field's shape or meaning changes;
+/// there's nothing to migrate yet, since this is the format's first version.
+fn migrate_node_schema(_snapshot: &mut GraphSnapshot, _from_version: u16) {
+}
+
+/// Checks `envelope`'s magic and versions against what this build supports, migrating
+/// `envelope.graph` forward in place if it's an older-but-supported node schema.
+pub fn negotiate_and_migrate(envelope: &mut GraphEnvelope) -> Result<(), FlowStatusCode> {
+    if envelope.magic != GRAPH_SNAPSHOT_MAGIC {
+        return Err(FlowStatusCode::InvalidArgument);
+    }
+    if envelope.format_version > CURRENT_FORMAT_VERSION {
+        // Forward-incompatible: this build is older than whatever wrote the snapshot.
+        return Err(FlowStatusCode::GraphVersionNotSupported);
+    }
+    if envelope.node_schema_version > CURRENT_NODE_SCHEMA_VERSION ||
+       envelope.node_schema_version < MIN_SUPPORTED_NODE_SCHEMA_VERSION {
+        return Err(FlowStatusCode::GraphVersionNotSupported);
+    }
+    if envelope.node_schema_version < CURRENT_NODE_SCHEMA_VERSION {
+        migrate_node_schema(&mut envelope.graph, envelope.node_schema_version);
+        envelope.node_schema_version = CURRENT_NODE_SCHEMA_VERSION;
+    }
+    Ok(())
+}
+
+/// True if `a` and `b` describe the same graph structure - same nodes in the same stages, same
+/// edges - ignoring cost accounting and cache digests, which change on every execution without
+/// the shape of the graph (what `job_notify_graph_changed` cares about) changing at all.
+pub fn structurally_equal(a: &GraphSnapshot, b: &GraphSnapshot) -> bool {
+    a.nodes.len() == b.nodes.len() && a.edges.len() == b.edges.len() &&
+    a.nodes.iter().zip(b.nodes.iter()).all(|(x, y)| {
+        x.index == y.index && x.stage == y.stage && x.has_dimensions == y.has_dimensions
+    }) &&
+    a.edges.iter().zip(b.edges.iter()).all(|(x, y)| {
+        x.from == y.from && x.to == y.to && x.kind == y.kind
+    })
+}
+
+/// Re-applies a (version-negotiated) snapshot's per-node state onto `g` - a graph already
+/// rebuilt from the same recipe the snapshot was taken from, so topology and `frame_est`/
+/// `type_digest_seed` already match. Nodes the snapshot doesn't mention (or that no longer
+/// exist) are left untouched.
+pub fn apply_snapshot_to_graph(g: &mut Graph, envelope: &GraphEnvelope) -> Result<(), FlowStatusCode> {
+    for node_snapshot in &envelope.graph.nodes {
+        if let Some(n) = g.node_weight_mut(node_index(node_snapshot.index as usize)) {
+            n.stage = node_snapshot.stage.to_stage();
+            n.cost.wall_ticks = node_snapshot.cost.wall_ticks;
+            n.cost.peak_bytes = node_snapshot.cost.peak_bytes;
+            n.cost.reused_bytes = node_snapshot.cost.reused_bytes;
+            n.cache_digest = node_snapshot.cache_digest.map(|(a, b)| NodeDigest(a, b));
+        }
+    }
+    Ok(())
+}
@@ -1,10 +1,14 @@
 use ::preludes::from_std::*;
 use ::std;
+use std::fmt;
 use num::{One, Zero};
 use num::bigint::{BigInt, Sign};
 use sha2::{Sha512, Digest};
-use ::chrono::{DateTime,FixedOffset};
+use ::chrono::{DateTime,FixedOffset,Duration};
 use unicase::UniCase;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::scalar::Scalar;
 
 use errors::*;
 use errors::Result;
@@ -17,12 +21,268 @@ use errors::Result;
 
 struct LicenseManager;
 
-struct LicenseFetcher;
-struct LicenseComputation;
-struct LicenseCache;
+/// An injection point for "what time is it", so `LicenseFetcher`'s refresh/grace-period logic
+/// can be unit-tested without relying on the real clock.
+trait Clock{
+    fn now(&self) -> DateTime<FixedOffset>;
+}
+struct SystemClock;
+impl Clock for SystemClock{
+    fn now(&self) -> DateTime<FixedOffset>{
+        ::chrono::Utc::now().with_timezone(&FixedOffset::east(0))
+    }
+}
+
+/// An injection point for the actual network call, so `LicenseFetcher` can be unit-tested
+/// without a real license server. `post` sends the request body to `url` and returns the
+/// response body, both opaque UTF-8 byte buffers as far as this trait is concerned.
+trait LicenseHttpClient{
+    fn post(&self, url: &str, body: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Fetches and refreshes remote (`is_must_be_fetched()`) licenses: POSTs the license id and a
+/// machine/secret fingerprint to each of `license_servers()` in turn, verifies whatever comes
+/// back against `trusted_keys`, and records it in a `LicenseCache` keyed by id.
+struct LicenseFetcher<'a>{
+    trusted_keys: &'a [TrustedKey],
+}
+impl<'a> LicenseFetcher<'a>{
+    fn new(trusted_keys: &'a [TrustedKey]) -> LicenseFetcher<'a>{
+        LicenseFetcher{ trusted_keys: trusted_keys }
+    }
+
+    /// True once `CheckLicenseIntervalMinutes` has elapsed since the last successful check-in
+    /// (or no check-in has ever succeeded).
+    fn due_for_refresh(&self, placeholder: &LicenseParser, clock: &Clock, cache: &LicenseCache) -> bool{
+        let interval_minutes = placeholder.check_license_interval_minutes().unwrap_or(60) as i64;
+        match cache.last_successful_check(placeholder.id()){
+            Some(last) => clock.now() >= last + Duration::minutes(interval_minutes),
+            None => true,
+        }
+    }
+
+    /// True if the cached copy of this license is still inside `NetworkGraceMinutes`, so a
+    /// currently-unreachable license server shouldn't interrupt the caller.
+    fn within_grace_period(&self, placeholder: &LicenseParser, clock: &Clock, cache: &LicenseCache) -> bool{
+        match (placeholder.network_grace_minutes(), cache.last_successful_check(placeholder.id())){
+            (Some(grace_minutes), Some(last)) => clock.now() <= last + Duration::minutes(grace_minutes as i64),
+            _ => false,
+        }
+    }
+
+    /// Tries each of `placeholder`'s `LicenseServers()` in turn (backing off to the next on
+    /// any failure - unreachable server, bad response, bad signature), returning the first
+    /// verified `LicenseBlob` and recording it in `cache`. If every server fails, returns the
+    /// last error encountered (or a "no servers configured" error if the list was empty).
+    fn fetch(&self, placeholder: &LicenseParser, fingerprint: &str, http: &LicenseHttpClient,
+              clock: &Clock, cache: &mut LicenseCache) -> Result<LicenseBlob>{
+        let request_body = format!("id={}&fingerprint={}", placeholder.id(), fingerprint);
+        let mut last_error = None;
+        for server in placeholder.license_servers(){
+            let attempt = http.post(server, request_body.as_bytes())
+                .chain_err(|| format!("Failed to reach license server {}", server))
+                .and_then(|response_bytes| {
+                    let response_str = str::from_utf8(&response_bytes)
+                        .chain_err(|| format!("License server {} did not return UTF-8", server))?;
+                    LicenseBlob::deserialize(self.trusted_keys, response_str)
+                });
+            match attempt {
+                Ok(blob) => {
+                    let now = clock.now();
+                    cache.store(placeholder.id(), &blob.original, now);
+                    return Ok(blob);
+                }
+                Err(e) => { last_error = Some(e); }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::from_kind(
+            ErrorKind::LicenseCorrupted(format!("License {} has no LicenseServers configured.", placeholder.id())))))
+    }
+
+    /// Refreshes `placeholder` if it's due, falling back to the cached copy (refreshed or not)
+    /// while still within the network grace period, and only failing once that grace period
+    /// has elapsed with no reachable server.
+    fn fetch_or_use_cache(&self, placeholder: &LicenseParser, fingerprint: &str, http: &LicenseHttpClient,
+                           clock: &Clock, cache: &mut LicenseCache) -> Result<LicenseBlob>{
+        if self.due_for_refresh(placeholder, clock, cache) {
+            match self.fetch(placeholder, fingerprint, http, clock, cache){
+                Ok(blob) => return Ok(blob),
+                Err(e) => {
+                    if let Some(cached) = cache.cached_license_text(placeholder.id()).map(|s| s.to_owned()) {
+                        if self.within_grace_period(placeholder, clock, cache) {
+                            return LicenseBlob::deserialize(self.trusted_keys, &cached);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        if let Some(cached) = cache.cached_license_text(placeholder.id()).map(|s| s.to_owned()) {
+            return LicenseBlob::deserialize(self.trusted_keys, &cached);
+        }
+        self.fetch(placeholder, fingerprint, http, clock, cache)
+    }
+}
+
+/// A fetch-timestamp cache for remote licenses, keyed by license id. `LicenseComputation`
+/// consults this to decide whether an expired-but-remote license is still within its
+/// `NetworkGraceMinutes` window.
+struct LicenseCache{
+    last_successful_check: HashMap<String, DateTime<FixedOffset>>,
+    /// The raw `id:data:signature` text of the last license successfully fetched for each id,
+    /// kept so `LicenseFetcher` can serve a cached copy while within the network grace period.
+    cached_license_text: HashMap<String, String>,
+}
+impl LicenseCache{
+    fn new() -> LicenseCache{
+        LicenseCache{ last_successful_check: HashMap::new(), cached_license_text: HashMap::new() }
+    }
+    fn record_successful_check(&mut self, id: &str, when: DateTime<FixedOffset>){
+        self.last_successful_check.insert(id.to_owned(), when);
+    }
+    fn last_successful_check(&self, id: &str) -> Option<DateTime<FixedOffset>>{
+        self.last_successful_check.get(id).cloned()
+    }
+    fn store(&mut self, id: &str, license_text: &str, when: DateTime<FixedOffset>){
+        self.cached_license_text.insert(id.to_owned(), license_text.to_owned());
+        self.record_successful_check(id, when);
+    }
+    fn cached_license_text(&self, id: &str) -> Option<&str>{
+        self.cached_license_text.get(id).map(|s| s.as_ref())
+    }
+}
+
+/// The verdict produced by `LicenseComputation::compute`, carrying the dates and message
+/// needed for callers to surface an actionable diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LicenseValidity{
+    Valid,
+    ValidWithMessage(String),
+    Expired{ expired: DateTime<FixedOffset>, message: Option<String> },
+    Invalid(String),
+}
+
+/// Decides whether a parsed license covers this build, right now. Combines the trusted build
+/// date (to honor perpetual-with-subscription licenses even past `Expires`) with the current
+/// clock and, for remote licenses, a `LicenseCache` of the last successful check-in.
+struct LicenseComputation{
+    build_date: DateTime<FixedOffset>,
+}
+impl LicenseComputation{
+    /// Allowance for clock skew between the signing server and this machine when deciding
+    /// whether a license claims to be issued in the future.
+    const ISSUED_SKEW_MINUTES: i64 = 5;
+
+    fn new(build_date: DateTime<FixedOffset>) -> LicenseComputation{
+        LicenseComputation{ build_date: build_date }
+    }
+
+    fn compute(&self, now: DateTime<FixedOffset>, license: &LicenseParser, cache: Option<&LicenseCache>) -> LicenseValidity{
+        if license.is_revoked(){
+            return LicenseValidity::Invalid(license.message().unwrap_or("This license has been revoked.").to_owned());
+        }
+        if let Some(issued) = license.issued(){
+            if issued > now + Duration::minutes(LicenseComputation::ISSUED_SKEW_MINUTES){
+                return LicenseValidity::Invalid(format!("License {} is issued in the future ({}); check this machine's clock.", license.id(), issued));
+            }
+        }
+        if !license.features().iter().any(|f| UniCase::new(*f) == UniCase::new("imageflow")){
+            return LicenseValidity::Invalid(format!("License {} does not cover the 'imageflow' product.", license.id()));
+        }
+
+        // Perpetual-with-subscription: this build remains covered as long as it predates the
+        // subscription cutoff, even once the license's own `Expires` date has passed.
+        if let Some(subscription_expires) = license.subscription_expiration_date(){
+            if self.build_date <= subscription_expires {
+                return self.valid_verdict(license);
+            }
+        }
+
+        if let Some(expires) = license.expires(){
+            if now > expires {
+                if let (Some(grace_minutes), Some(cache)) = (license.network_grace_minutes(), cache){
+                    if let Some(last_checked) = cache.last_successful_check(license.id()){
+                        if now <= last_checked + Duration::minutes(grace_minutes as i64){
+                            return self.valid_verdict(license);
+                        }
+                    }
+                }
+                return LicenseValidity::Expired{ expired: expires, message: license.expiry_message().map(|s| s.to_owned()) };
+            }
+        }
+
+        self.valid_verdict(license)
+    }
+
+    fn valid_verdict(&self, license: &LicenseParser) -> LicenseValidity{
+        match license.message(){
+            Some(m) => LicenseValidity::ValidWithMessage(m.to_owned()),
+            None => LicenseValidity::Valid,
+        }
+    }
+}
 //trait LicenseClock;
-struct LicensePair
-;
+
+/// Checks that `child`'s validity window is fully contained within `parent`'s:
+/// `parent.issued <= child.issued && child.expires <= parent.expires`. A license with no
+/// `Issued`/`Expires` field is treated as unbounded on that side.
+fn validate_bounds(parent: &LicenseBlob, child: &LicenseBlob) -> Result<()>{
+    let parent_issued = parent.fields.issued();
+    let parent_expires = parent.fields.expires();
+    let child_issued = child.fields.issued();
+    let child_expires = child.fields.expires();
+
+    if let (Some(p), Some(c)) = (parent_issued, child_issued) {
+        if c < p {
+            return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!(
+                "License {} (issued {}) escapes the validity window of its parent {} (issued {})",
+                child.fields.id(), c, parent.fields.id(), p))));
+        }
+    }
+    if let (Some(p), Some(c)) = (parent_expires, child_expires) {
+        if c > p {
+            return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!(
+                "License {} (expires {}) escapes the validity window of its parent {} (expires {})",
+                child.fields.id(), c, parent.fields.id(), p))));
+        }
+    }
+    Ok(())
+}
+
+/// Upper bound on how many `Intermediate:` links `validate_chain` will follow. Every link costs
+/// a full signature verification, and nothing about the license format otherwise bounds how many
+/// of them a malformed or adversarial chain could declare - without this cap, a license crafted
+/// with enough `Intermediate:` links makes chain validation do an unbounded amount of signature
+/// verification work for one `validate_chain` call.
+const MAX_CHAIN_DEPTH: usize = 16;
+
+/// Deserializes and verifies an intermediate/root-delegated license chain: `license`, its
+/// `Intermediate:` parent, that parent's `Intermediate:` parent, and so on up to a root license
+/// with no `Intermediate:` field. Every link's signature is checked against `trusted_keys`, and
+/// every adjacent pair must satisfy the nested validity-bound invariant. Fails with
+/// `ErrorKind::LicenseCorrupted` if the chain is deeper than `MAX_CHAIN_DEPTH`.
+fn validate_chain(trusted_keys: &[TrustedKey], license: &str) -> Result<Vec<LicenseBlob>>{
+    let mut chain = vec![LicenseBlob::deserialize(trusted_keys, license)?];
+    loop {
+        let intermediate = {
+            let current = chain.last().unwrap();
+            match current.fields.get("Intermediate") {
+                Some(s) => s.to_owned(),
+                None => break,
+            }
+        };
+        // `chain.len() - 1` links have been followed so far; following this one must not
+        // exceed `MAX_CHAIN_DEPTH`.
+        if chain.len() > MAX_CHAIN_DEPTH {
+            return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!(
+                "License chain is deeper than the maximum of {} intermediate licenses.", MAX_CHAIN_DEPTH))));
+        }
+        let parent = LicenseBlob::deserialize(trusted_keys, &intermediate)?;
+        validate_bounds(&parent, chain.last().unwrap())?;
+        chain.push(parent);
+    }
+    Ok(chain)
+}
 
 
 // expired
@@ -90,6 +350,13 @@ impl LicenseParser{
     pub fn subscription_expiration_date(&self) -> Option<DateTime<FixedOffset>>{
         self.subscription_expiration_date
     }
+    /// The signature algorithm the license was signed with. Defaults to `Rsa4096Sha512` for
+    /// legacy licenses that predate the `Algorithm:`/`Alg:` field.
+    pub fn algorithm(&self) -> Algorithm{
+        self.get("Algorithm").or_else(|| self.get("Alg"))
+            .and_then(Algorithm::parse)
+            .unwrap_or(Algorithm::Rsa4096Sha512)
+    }
     pub fn is_remote_placeholder(&self) -> bool{
         self.get("Kind").map(|s| UniCase::new(s) == UniCase::new("id")).unwrap_or(false)
     }
@@ -105,6 +372,11 @@ impl LicenseParser{
     pub fn network_grace_minutes(&self) -> Option<i32>{
         self.parse_int("NetworkGraceMinutes")
     }
+    /// The short key-tag identifying which trusted key signed this license, if the license
+    /// carries one. Legacy licenses lacking a `KeyTag:` field fall back to a full key scan.
+    pub fn key_tag(&self) -> Option<u16>{
+        self.get("KeyTag").and_then(|s| u16::from_str_radix(s.trim_left_matches("0x"), 16).ok())
+    }
     pub fn check_license_interval_minutes(&self) -> Option<i32>{
         self.parse_int("CheckLicenseIntervalMinutes")
     }
@@ -143,8 +415,26 @@ struct LicenseBlob{
 fields: LicenseParser
 }
 
+/// Human-readable prefix for the bech32 license-key encoding, as in `ifl1...`.
+const LICENSE_KEY_HRP: &str = "ifl";
+
 impl LicenseBlob{
-    pub fn deserialize(trusted_keys: &[RSADecryptPublic], license: &str) -> Result<LicenseBlob>{
+    /// Parses a license key in either of two formats, auto-detected from its shape:
+    ///
+    /// * `id:base64(data):base64(signature)` - the original format.
+    /// * `ifl1...` - a bech32-checksummed encoding (see `deserialize_bech32`), whose BCH
+    ///   checksum catches typos and transpositions that base64 can't, at the cost of being
+    ///   usable only for keys short enough to read/type by hand.
+    pub fn deserialize(trusted_keys: &[TrustedKey], license: &str) -> Result<LicenseBlob>{
+        let license = license.trim();
+        if license.to_lowercase().starts_with(&format!("{}1", LICENSE_KEY_HRP)) {
+            LicenseBlob::deserialize_bech32(trusted_keys, license)
+        } else {
+            LicenseBlob::deserialize_base64(trusted_keys, license)
+        }
+    }
+
+    fn deserialize_base64(trusted_keys: &[TrustedKey], license: &str) -> Result<LicenseBlob>{
         let parts = license.split(":").map(|s| s.trim().to_owned()).collect::<Vec<String>>();
         if parts.len() < 2{
             return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!("License incomplete: not enough ':' delimited segments found.\n{}", license))));
@@ -154,16 +444,51 @@ impl LicenseBlob{
         let signature_bytes = ::base64::decode(parts[parts.len() - 1].as_bytes())
             .chain_err(|| Error::from_kind(ErrorKind::LicenseCorrupted(format!("Second-to-last segment is not valid base 64.\n{}", license))))?;
 
-        let signature_valid = LicenseBlob::validate_signature(&data_bytes, &signature_bytes, trusted_keys)
+        LicenseBlob::from_data_and_signature(trusted_keys, license, data_bytes, signature_bytes)
+    }
+
+    /// Decodes a bech32-encoded license key: HRP `ifl`, separator `1`, then a base32 payload
+    /// carrying a big-endian `u32` data length followed by `data || signature`, with a trailing
+    /// BCH checksum. Parsing the string verifies the checksum (which covers the HRP), and the
+    /// HRP is then checked against `ifl` before the payload is converted, so a misread prefix
+    /// is rejected immediately rather than producing garbage bytes.
+    fn deserialize_bech32(trusted_keys: &[TrustedKey], license: &str) -> Result<LicenseBlob>{
+        let decoded: ::bech32::Bech32 = license.parse()
+            .chain_err(|| Error::from_kind(ErrorKind::LicenseCorrupted(format!("Malformed license key (bad bech32 checksum or prefix).\n{}", license))))?;
+        if decoded.hrp != LICENSE_KEY_HRP {
+            return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!("License key has unrecognized prefix '{}'.\n{}", decoded.hrp, license))));
+        }
+        let payload = ::bech32::convert_bits(&decoded.data, 5, 8, false)
+            .chain_err(|| Error::from_kind(ErrorKind::LicenseCorrupted(format!("Malformed license key payload.\n{}", license))))?;
+        if payload.len() < 4 {
+            return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!("License key payload too short.\n{}", license))));
+        }
+        let data_len = ((payload[0] as usize) << 24) | ((payload[1] as usize) << 16)
+            | ((payload[2] as usize) << 8) | (payload[3] as usize);
+        if payload.len() < 4 + data_len {
+            return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!("License key payload shorter than its declared data length.\n{}", license))));
+        }
+        let data_bytes = payload[4..4 + data_len].to_vec();
+        let signature_bytes = payload[4 + data_len..].to_vec();
+
+        LicenseBlob::from_data_and_signature(trusted_keys, license, data_bytes, signature_bytes)
+    }
+
+    fn from_data_and_signature(trusted_keys: &[TrustedKey], license: &str, data_bytes: Vec<u8>, signature_bytes: Vec<u8>) -> Result<LicenseBlob>{
+        // The algorithm field lives inside the signed data itself; reading it before the
+        // signature is checked is safe because any tampering changes the hash and fails below.
+        let data_string = str::from_utf8(&data_bytes).chain_err(||"License contents must be valid UTF-8 bytes")?;
+        let fields = LicenseParser::new(data_string)?;
+        let algorithm = fields.algorithm();
+
+        let key_tag = fields.key_tag();
+        let signature_valid = LicenseBlob::validate_signature(&data_bytes, &signature_bytes, algorithm, key_tag, trusted_keys)
             .chain_err(|| Error::from_kind(ErrorKind::LicenseCorrupted(format!("License signature too long.\n{}", license))))?;
 
         if !signature_valid {
             return Err(Error::from_kind(ErrorKind::LicenseCorrupted(format!("Decrypted license signature does not match license contents.\n{}", license))));
         }
 
-        let data_string = str::from_utf8(&data_bytes).chain_err(||"License contents must be valid UTF-8 bytes")?;
-
-        let fields = LicenseParser::new(data_string)?;
         Ok(
             LicenseBlob{
                 original: license.to_owned(),
@@ -174,13 +499,21 @@ impl LicenseBlob{
         )
     }
 
-    fn validate_signature(data: &[u8], signature: &[u8], trusted_keys: &[RSADecryptPublic]) -> Result<bool>{
-        let mut hasher = Sha512::default();
-        hasher.input(data);
-        let digest = hasher.result();
-        for rsa in trusted_keys{
-            let decrypted = rsa.decrypt_public(signature)?;
-            if decrypted.as_slice() == digest.as_slice() {
+    fn validate_signature(data: &[u8], signature: &[u8], algorithm: Algorithm, key_tag: Option<u16>, trusted_keys: &[TrustedKey]) -> Result<bool>{
+        // A tagged license names its signer directly, so verification is O(1) rather than a
+        // trial-decrypt against every trusted key.
+        if let Some(tag) = key_tag {
+            if let Some(key) = trusted_keys.iter().find(|k| k.algorithm() == algorithm && k.key_tag() == tag) {
+                return key.verify(data, signature);
+            }
+            // Fall through to the full scan for legacy licenses whose tag doesn't (yet) match
+            // a key we know about, e.g. after a key rotation the license hasn't caught up to.
+        }
+        for key in trusted_keys{
+            if key.algorithm() != algorithm {
+                continue;
+            }
+            if key.verify(data, signature)? {
                 return Ok(true);
             }
         }
@@ -189,6 +522,163 @@ impl LicenseBlob{
 
 }
 
+/// License signature algorithms supported by `LicenseBlob`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm{
+    Rsa4096Sha512,
+    Ed25519,
+}
+impl Algorithm{
+    fn parse(s: &str) -> Option<Algorithm>{
+        match UniCase::new(s) {
+            ref v if *v == UniCase::new("RSA4096-SHA512") || *v == UniCase::new("RSA") => Some(Algorithm::Rsa4096Sha512),
+            ref v if *v == UniCase::new("Ed25519") => Some(Algorithm::Ed25519),
+            _ => None
+        }
+    }
+}
+
+/// An Ed25519 public key trusted to sign licenses.
+pub struct Ed25519PublicKey{
+    point: ::curve25519_dalek::edwards::EdwardsPoint,
+    bytes: [u8; 32]
+}
+impl Ed25519PublicKey{
+    pub fn from_bytes(bytes: [u8; 32]) -> Option<Ed25519PublicKey>{
+        CompressedEdwardsY(bytes).decompress().map(|point| Ed25519PublicKey { point, bytes })
+    }
+
+    /// Verifies a detached Ed25519 signature `(R || s)` over `message`, per RFC 8032:
+    /// `k = SHA512(R || A || message) mod L`, accept iff `s*B == R + k*A`.
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<bool>{
+        if signature.len() != 64 {
+            return Ok(false);
+        }
+        let r_bytes = &signature[..32];
+        let s_bytes = &signature[32..];
+        let r = match CompressedEdwardsY::from_slice(r_bytes).decompress() {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+        let mut s_array = [0u8; 32];
+        s_array.copy_from_slice(s_bytes);
+        let s = match Scalar::from_canonical_bytes(s_array) {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+
+        let mut hasher = Sha512::default();
+        hasher.input(r_bytes);
+        hasher.input(&self.bytes);
+        hasher.input(message);
+        let k = Scalar::from_hash(hasher);
+
+        let lhs = &s * &ED25519_BASEPOINT_TABLE;
+        let rhs = r + k * self.point;
+        Ok(lhs.compress() == rhs.compress())
+    }
+}
+impl fmt::Debug for Ed25519PublicKey{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        write!(f, "Ed25519PublicKey({})", ::base64::encode(&self.bytes))
+    }
+}
+
+/// A key trusted to sign licenses, tagged by which algorithm it verifies.
+#[derive(Debug)]
+pub enum TrustedKey{
+    Rsa4096Sha512(RSADecryptPublic),
+    Ed25519(Ed25519PublicKey),
+}
+impl TrustedKey{
+    fn algorithm(&self) -> Algorithm{
+        match *self {
+            TrustedKey::Rsa4096Sha512(_) => Algorithm::Rsa4096Sha512,
+            TrustedKey::Ed25519(_) => Algorithm::Ed25519,
+        }
+    }
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool>{
+        match *self {
+            TrustedKey::Rsa4096Sha512(ref rsa) => {
+                let mut hasher = Sha512::default();
+                hasher.input(data);
+                let digest = hasher.result();
+                match rsa.decrypt_public_padded(signature)? {
+                    Some(encoded) => Ok(pkcs1_v15_sha512_digest_matches(&encoded, digest.as_slice())),
+                    None => Ok(false),
+                }
+            }
+            TrustedKey::Ed25519(ref key) => key.verify(data, signature),
+        }
+    }
+
+    /// A stable short identifier for this key, in the style of a DNSSEC key tag: the first
+    /// two bytes of `SHA-512(identity bytes)`, where the identity bytes are the RSA modulus's
+    /// big-endian encoding or the raw 32-byte Ed25519 public key.
+    fn key_tag(&self) -> u16{
+        let identity = match *self {
+            TrustedKey::Rsa4096Sha512(ref rsa) => rsa.modulus.to_bytes_be().1,
+            TrustedKey::Ed25519(ref key) => key.bytes.to_vec(),
+        };
+        let mut hasher = Sha512::default();
+        hasher.input(&identity);
+        let digest = hasher.result();
+        ((digest[0] as u16) << 8) | (digest[1] as u16)
+    }
+}
+
+/// The DER encoding of the SHA-512 `AlgorithmIdentifier` for PKCS#1 v1.5 `DigestInfo`,
+/// per RFC 3447 Appendix B.1 / RFC 8017. Followed by the raw 64-byte digest.
+const SHA512_DIGESTINFO_PREFIX: [u8; 19] = [
+    0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+    0x05, 0x00, 0x04, 0x40,
+];
+
+/// Verifies that `encoded` (the full-width, left-zero-padded RSA public operation output)
+/// is a valid RSASSA-PKCS1-v1_5 encoding of `digest`: `0x00 || 0x01 || PS || 0x00 || T`,
+/// where `PS` is at least 8 bytes of `0xFF` and `T` is the SHA-512 `DigestInfo`.
+///
+/// Rejects anything that doesn't match byte-for-byte, and compares the digest itself in
+/// constant time so a forged-but-structurally-valid padding can't be distinguished by timing.
+fn pkcs1_v15_sha512_digest_matches(encoded: &[u8], digest: &[u8]) -> bool {
+    let t_len = SHA512_DIGESTINFO_PREFIX.len() + digest.len();
+    // 0x00 || 0x01 || PS(>=8 bytes of 0xFF) || 0x00 || T
+    if encoded.len() < 2 + 8 + 1 + t_len {
+        return false;
+    }
+    if encoded[0] != 0x00 || encoded[1] != 0x01 {
+        return false;
+    }
+    let t_start = encoded.len() - t_len;
+    let ps = &encoded[2..t_start - 1];
+    if ps.len() < 8 || ps.iter().any(|&b| b != 0xFF) {
+        return false;
+    }
+    if encoded[t_start - 1] != 0x00 {
+        return false;
+    }
+    let t = &encoded[t_start..];
+    if t[..SHA512_DIGESTINFO_PREFIX.len()] != SHA512_DIGESTINFO_PREFIX[..] {
+        return false;
+    }
+    let found_digest = &t[SHA512_DIGESTINFO_PREFIX.len()..];
+    constant_time_eq(found_digest, digest)
+}
+
+/// Compares two equal-length byte slices without branching on their contents, so comparison
+/// time doesn't leak how many leading bytes matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+#[derive(Debug)]
 pub struct RSADecryptPublic {
     modulus: BigInt,
     exponent: BigInt
@@ -249,30 +739,54 @@ impl RSADecryptPublic{
             Ok(self.mod_pow(&input).to_bytes_be().1.into_iter().skip_while(|v| *v != 0).skip(1).collect())
         }
     }
+
+    /// Number of bytes in the modulus (`k` in RFC 3447 terms).
+    fn modulus_byte_len(&self) -> usize {
+        (self.modulus.bits() + 7) / 8
+    }
+
+    /// Performs the RSA public-key operation and left-pads the result to exactly `k` bytes
+    /// (the modulus byte length), so the PKCS#1 v1.5 padding structure can be checked without
+    /// losing leading zero bytes. Fails with `RsaDecryptInputLargerThanModulus` if the input is
+    /// not smaller than the modulus, and returns `Ok(None)` if the result doesn't fit in `k` bytes.
+    pub fn decrypt_public_padded(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>>{
+        let input = BigInt::from_bytes_be(Sign::Plus, bytes);
+        if input >= self.modulus{
+            return Err(Error::from_kind(ErrorKind::RsaDecryptInputLargerThanModulus));
+        }
+        let k = self.modulus_byte_len();
+        let unpadded = self.mod_pow(&input).to_bytes_be().1;
+        if unpadded.len() > k {
+            return Ok(None);
+        }
+        let mut encoded = vec![0u8; k - unpadded.len()];
+        encoded.extend_from_slice(&unpadded);
+        Ok(Some(encoded))
+    }
 }
 
-fn get_production_keys() -> Vec<RSADecryptPublic> {
-    vec![RSADecryptPublic::from_byte_strings(
+fn get_production_keys() -> Vec<TrustedKey> {
+    vec![TrustedKey::Rsa4096Sha512(RSADecryptPublic::from_byte_strings(
         b"23949488589991837273662465276682907968730706102086698017736172318753209677546629836371834786541857453052840819693021342491826827766290334135101781149845778026274346770115575977554682930349121443920608458091578262535319494351868006252977941758848154879863365934717437651379551758086088085154566157115250553458305198857498335213985131201841998493838963767334138323078497945594454883498534678422546267572587992510807296283688571798124078989780633040004809178041347751023931122344529856055566400640640925760832450260419468881471181281199910469396775343083815780600723550633987799763107821157001135810564362648091574582493",
-        b"65537").unwrap(),
-         RSADecryptPublic::from_byte_strings(
+        b"65537").unwrap()),
+         TrustedKey::Rsa4096Sha512(RSADecryptPublic::from_byte_strings(
              b"20966000569757071862106887100142448229133877611190126160168597284259733824510172534126967070490592659952430888203435031779696121874348777439846786968121542858840906429510085119585674950522992116110440180288728612219347325636018396716507682924594303420147925518492731883007123328081986113438120311956235689236820190735716844178839961449198918585485277306636638238163410140728079481083558191670535479781738412622557832581113291858559860935145319768483825412681366230852014952837750160226558508220374106696447994610354318517561059830141995002511253671974534953764078640650030953288533566233172651498868658899945417935381",
-             b"65537").unwrap()]
+             b"65537").unwrap())]
 
 }
 
-fn get_test_keys() -> Vec<RSADecryptPublic> {
-    vec![RSADecryptPublic::from_byte_strings(
+fn get_test_keys() -> Vec<TrustedKey> {
+    vec![TrustedKey::Rsa4096Sha512(RSADecryptPublic::from_byte_strings(
         b"21403964489040138713896545869406851734432500305180577929806228393671667423170541918856531956008546071841016201645150244452266439995041173092354230946610429300967887006960186647111152810965360763586210200652502467947786453111507369142658284220331513416234497960844309808252643534631142917589553418044306073242485021092396181183125381004682521853943025560860753079004948017667604884278401445729443478586697229583656851019218046599746243419376456426788044497274378001221965538712352348475726349124652450874653832672820100829574087311416068166524423905971193163418806721436095962165082262760557869093554827824418663362349",
-        b"65537").unwrap()]
+        b"65537").unwrap())]
 }
 
 lazy_static!{
-        pub static ref PRODUCTION_KEYS: Vec<RSADecryptPublic> = get_production_keys();
+        pub static ref PRODUCTION_KEYS: Vec<TrustedKey> = get_production_keys();
 
-        pub static ref TEST_KEYS: Vec<RSADecryptPublic> = get_test_keys();
+        pub static ref TEST_KEYS: Vec<TrustedKey> = get_test_keys();
 
-        pub static ref ALL_KEYS: Vec<RSADecryptPublic> = {
+        pub static ref ALL_KEYS: Vec<TrustedKey> = {
             get_production_keys().into_iter().chain(get_test_keys().into_iter()).collect()
         };
 
@@ -286,8 +800,8 @@ mod test{
     #[test]
     fn test_generic(){
 
-        let rsa = RSADecryptPublic::from_byte_strings(b"28178177427582259905122756905913963624440517746414712044433894631438407111916149031583287058323879921298234454158166031934230083094710974550125942791690254427377300877691173542319534371793100994953897137837772694304619234054383162641475011138179669415510521009673718000682851222831185756777382795378538121010194881849505437499638792289283538921706236004391184253166867653735050981736002298838523242717690667046044130539971131293603078008447972889271580670305162199959939004819206804246872436611558871928921860176200657026263241409488257640191893499783065332541392967986495144643652353104461436623253327708136399114561",
-                                                      b"65537").expect("RSA parameters must be positive integers in base 10");
+        let rsa = TrustedKey::Rsa4096Sha512(RSADecryptPublic::from_byte_strings(b"28178177427582259905122756905913963624440517746414712044433894631438407111916149031583287058323879921298234454158166031934230083094710974550125942791690254427377300877691173542319534371793100994953897137837772694304619234054383162641475011138179669415510521009673718000682851222831185756777382795378538121010194881849505437499638792289283538921706236004391184253166867653735050981736002298838523242717690667046044130539971131293603078008447972889271580670305162199959939004819206804246872436611558871928921860176200657026263241409488257640191893499783065332541392967986495144643652353104461436623253327708136399114561",
+                                                      b"65537").expect("RSA parameters must be positive integers in base 10"));
 
 
         let blob = LicenseBlob::deserialize(&[rsa],"localhost:RG9tYWluOiBsb2NhbGhvc3QKT3duZXI6IEV2ZXJ5b25lCklzc3VlZDogMjAxNS0wMy0yOFQwOTozNjo1OVoKRmVhdHVyZXM6IFI0RWxpdGUgUjRDcmVhdGl2ZSBSNFBlcmZvcm1hbmNlCg==:h6D+kIXbF3qmvmW2gDpb+b4gdxBjnrkZLvSzXmEnqKAywNJNpTdFekpTOB4SwU14WbTeVyWwvFngHax7WuHBV+0WkQ5lDqKFaRW32vj8CJQeG8Wvnyj9PaNGaS/FpKhNjZbDEmh3qqirBp2NR0bpN4QbhP9NMy7+rOMo0nynAruwWvJKCnuf7mWWdb9a5uTZO9OUcSeS/tY8QaNeIhaCnhPe0Yx9qvOXe5nMnl10CR9ur+EtS54d1qzBGHqN/3oFhiB+xlqNELwz23qR4c8HxbTEyNarkG4CZx8CbbgJfHmPxAYGJTTBTPJ+cdah8MJR16Ta36cRZ2Buy8XYo/nf1g==");
@@ -297,4 +811,115 @@ mod test{
             assert!(false);
         }
     }
+
+    // Same rsa key/license text as `test_generic`, reused as the fixture for `LicenseFetcher`'s
+    // fake-`Clock`/fake-`LicenseHttpClient` tests below.
+    fn localhost_trusted_key() -> TrustedKey{
+        TrustedKey::Rsa4096Sha512(RSADecryptPublic::from_byte_strings(b"28178177427582259905122756905913963624440517746414712044433894631438407111916149031583287058323879921298234454158166031934230083094710974550125942791690254427377300877691173542319534371793100994953897137837772694304619234054383162641475011138179669415510521009673718000682851222831185756777382795378538121010194881849505437499638792289283538921706236004391184253166867653735050981736002298838523242717690667046044130539971131293603078008447972889271580670305162199959939004819206804246872436611558871928921860176200657026263241409488257640191893499783065332541392967986495144643652353104461436623253327708136399114561",
+                                                 b"65537").expect("RSA parameters must be positive integers in base 10"))
+    }
+    fn localhost_license_text() -> &'static str{
+        "localhost:RG9tYWluOiBsb2NhbGhvc3QKT3duZXI6IEV2ZXJ5b25lCklzc3VlZDogMjAxNS0wMy0yOFQwOTozNjo1OVoKRmVhdHVyZXM6IFI0RWxpdGUgUjRDcmVhdGl2ZSBSNFBlcmZvcm1hbmNlCg==:h6D+kIXbF3qmvmW2gDpb+b4gdxBjnrkZLvSzXmEnqKAywNJNpTdFekpTOB4SwU14WbTeVyWwvFngHax7WuHBV+0WkQ5lDqKFaRW32vj8CJQeG8Wvnyj9PaNGaS/FpKhNjZbDEmh3qqirBp2NR0bpN4QbhP9NMy7+rOMo0nynAruwWvJKCnuf7mWWdb9a5uTZO9OUcSeS/tY8QaNeIhaCnhPe0Yx9qvOXe5nMnl10CR9ur+EtS54d1qzBGHqN/3oFhiB+xlqNELwz23qR4c8HxbTEyNarkG4CZx8CbbgJfHmPxAYGJTTBTPJ+cdah8MJR16Ta36cRZ2Buy8XYo/nf1g=="
+    }
+    fn placeholder(check_interval_minutes: i32, grace_minutes: i32) -> LicenseParser{
+        LicenseParser::new(&format!("Id: localhost\nLicenseServers: http://fake.example\nCheckLicenseIntervalMinutes: {}\nNetworkGraceMinutes: {}\n",
+                                     check_interval_minutes, grace_minutes)).unwrap()
+    }
+
+    struct FixedClock{ fixed: DateTime<FixedOffset> }
+    impl Clock for FixedClock{
+        fn now(&self) -> DateTime<FixedOffset>{ self.fixed }
+    }
+    fn at_minute(minute: i64) -> DateTime<FixedOffset>{
+        DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap() + Duration::minutes(minute)
+    }
+
+    /// Returns a scripted response (success or failure) the first time `post` is called, then
+    /// panics if called again - every test below expects at most one server to be tried.
+    struct ScriptedHttp{ result: std::cell::RefCell<Option<Result<Vec<u8>>>> }
+    impl ScriptedHttp{
+        fn ok(body: &str) -> ScriptedHttp{
+            ScriptedHttp{ result: std::cell::RefCell::new(Some(Ok(body.as_bytes().to_vec()))) }
+        }
+        fn err() -> ScriptedHttp{
+            ScriptedHttp{ result: std::cell::RefCell::new(Some(Err(
+                Error::from_kind(ErrorKind::LicenseCorrupted("simulated network failure".to_owned()))))) }
+        }
+    }
+    impl LicenseHttpClient for ScriptedHttp{
+        fn post(&self, _url: &str, _body: &[u8]) -> Result<Vec<u8>>{
+            self.result.borrow_mut().take().expect("ScriptedHttp::post called more than once")
+        }
+    }
+
+    #[test]
+    fn test_due_for_refresh_when_never_checked(){
+        let fetcher = LicenseFetcher::new(&[]);
+        let cache = LicenseCache::new();
+        let clock = FixedClock{ fixed: at_minute(0) };
+        assert!(fetcher.due_for_refresh(&placeholder(60, 120), &clock, &cache));
+    }
+
+    #[test]
+    fn test_due_for_refresh_respects_interval(){
+        let fetcher = LicenseFetcher::new(&[]);
+        let mut cache = LicenseCache::new();
+        cache.record_successful_check("localhost", at_minute(0));
+
+        assert!(!fetcher.due_for_refresh(&placeholder(60, 120), &FixedClock{ fixed: at_minute(59) }, &cache));
+        assert!(fetcher.due_for_refresh(&placeholder(60, 120), &FixedClock{ fixed: at_minute(61) }, &cache));
+    }
+
+    #[test]
+    fn test_within_grace_period(){
+        let fetcher = LicenseFetcher::new(&[]);
+        let mut cache = LicenseCache::new();
+        cache.record_successful_check("localhost", at_minute(0));
+
+        assert!(fetcher.within_grace_period(&placeholder(60, 120), &FixedClock{ fixed: at_minute(119) }, &cache));
+        assert!(!fetcher.within_grace_period(&placeholder(60, 120), &FixedClock{ fixed: at_minute(121) }, &cache));
+    }
+
+    #[test]
+    fn test_fetch_or_use_cache_fetches_when_due(){
+        let key = localhost_trusted_key();
+        let fetcher = LicenseFetcher::new(&[key]);
+        let mut cache = LicenseCache::new();
+        let http = ScriptedHttp::ok(localhost_license_text());
+        let clock = FixedClock{ fixed: at_minute(0) };
+
+        let blob = fetcher.fetch_or_use_cache(&placeholder(60, 120), "fingerprint", &http, &clock, &mut cache).unwrap();
+        assert_eq!(blob.fields.id(), "localhost");
+        assert_eq!(cache.last_successful_check("localhost"), Some(at_minute(0)));
+    }
+
+    #[test]
+    fn test_fetch_or_use_cache_falls_back_to_cache_within_grace_period(){
+        let key = localhost_trusted_key();
+        let fetcher = LicenseFetcher::new(&[key]);
+        let mut cache = LicenseCache::new();
+        cache.store("localhost", localhost_license_text(), at_minute(0));
+        let http = ScriptedHttp::err();
+
+        // Due for refresh (61 > 60 minute interval) but still within the 120 minute grace
+        // period, and the scripted server fails - should fall back to the cached copy.
+        let blob = fetcher.fetch_or_use_cache(&placeholder(60, 120), "fingerprint", &http,
+                                               &FixedClock{ fixed: at_minute(61) }, &mut cache).unwrap();
+        assert_eq!(blob.fields.id(), "localhost");
+    }
+
+    #[test]
+    fn test_fetch_or_use_cache_fails_once_grace_period_elapses(){
+        let key = localhost_trusted_key();
+        let fetcher = LicenseFetcher::new(&[key]);
+        let mut cache = LicenseCache::new();
+        cache.store("localhost", localhost_license_text(), at_minute(0));
+        let http = ScriptedHttp::err();
+
+        // Due for refresh and past the 120 minute grace period, with the scripted server
+        // failing - there's no safe fallback left.
+        let result = fetcher.fetch_or_use_cache(&placeholder(60, 120), "fingerprint", &http,
+                                                 &FixedClock{ fixed: at_minute(121) }, &mut cache);
+        assert!(result.is_err());
+    }
 }
\ No newline at end of file